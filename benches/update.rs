@@ -0,0 +1,86 @@
+//! Update cost per particle for dense flow-field pieces.
+//!
+//!     cargo bench --bench update [-- 10000 100000 300000]
+
+use particle_lib::config::parse_config;
+use particle_lib::emitter::Bounds;
+use particle_lib::world::World;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const STEPS: u32 = 20;
+
+/// A flow-field emitter kept topped up at `count` particles. With `churn`
+/// they live for five steps, so removal and refilling are part of the cost.
+fn world(count: usize, integrator: &str, churn: bool) -> World {
+    let text = format!(
+        r#"
+selected_emitters = ["e"]
+integrator = "{}"
+[emitters.e]
+randomize_position = true
+life_span = {}
+noise_field = true
+noise_scale = 0.005
+noise_strength = 0.1
+[emitters.e.emission]
+mode = "steady"
+rate = {}
+max_particles = {}
+"#,
+        integrator,
+        if churn { 10.0 } else { 1e9 },
+        count * 60,
+        count
+    );
+    let bounds = Bounds {
+        top: 300.0,
+        bottom: -300.0,
+        left: -300.0,
+        right: 300.0,
+    };
+    World::new(&parse_config(&text).unwrap(), bounds)
+}
+
+/// Average time per particle per step over `STEPS` steps.
+fn bench(count: usize, integrator: &str, churn: bool) -> Duration {
+    let mut world = world(count, integrator, churn);
+    world.step();
+    let start = Instant::now();
+    for _ in 0..STEPS {
+        world.step();
+        black_box(&world);
+    }
+    start.elapsed() / (STEPS * count as u32)
+}
+
+fn main() {
+    let counts: Vec<usize> = std::env::args()
+        .skip(1)
+        .filter_map(|arg| arg.parse().ok())
+        .collect();
+    let counts = if counts.is_empty() {
+        vec![10_000, 100_000, 300_000]
+    } else {
+        counts
+    };
+    println!(
+        "{:>10} {:>20} {:>6} {:>14} {:>12}",
+        "particles", "integrator", "churn", "per particle", "per step"
+    );
+    for count in counts {
+        for integrator in ["semi_implicit_euler", "rk4"] {
+            for churn in [false, true] {
+                let per_particle = bench(count, integrator, churn);
+                println!(
+                    "{:>10} {:>20} {:>6} {:>14?} {:>12?}",
+                    count,
+                    integrator,
+                    churn,
+                    per_particle,
+                    per_particle * count as u32
+                );
+            }
+        }
+    }
+}
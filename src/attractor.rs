@@ -0,0 +1,187 @@
+use super::config::{AttractorConfig, MoverConfig};
+use super::expression::Param;
+use super::mover::{Mover, MoverState};
+
+use nannou::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Distance, in pixels, at which an attractor's pull equals its `strength`.
+pub const REFERENCE_DISTANCE: f32 = 100.0;
+
+/// Particles closer than this are pulled as if they were this far away, so
+/// those passing over an attractor aren't flung off at huge speeds.
+pub const MIN_DISTANCE: f32 = 10.0;
+
+/// A point that pulls particles towards it, or pushes them away, harder the
+/// closer they are.
+#[derive(Debug, Clone)]
+pub struct Attractor {
+    name: String,
+    position: Point2,
+    mover: Option<Mover>,
+    strength: Param,
+    falloff: f32,
+    radius: Option<f32>,
+    // 1 to attract, -1 to repel.
+    sign: f32,
+}
+
+/// The parts of an `Attractor` that change as it runs.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AttractorState {
+    pub name: String,
+    pub mover: Option<MoverState>,
+}
+
+/// An attractor's effect at one instant. Cheap to copy into the update of
+/// every emitter it acts on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pull {
+    pub position: Point2,
+    /// Signed: negative pushes away.
+    pub strength: f32,
+    pub falloff: f32,
+    pub radius: f32,
+}
+
+impl Attractor {
+    pub fn from_config(
+        name: String,
+        config: AttractorConfig,
+        movers: &HashMap<String, MoverConfig>,
+    ) -> Self {
+        let config = config.with_defaults();
+        let mover = config
+            .mover
+            .as_ref()
+            .and_then(|m| movers.get(m))
+            .map(|m| Mover::from_config(config.mover.clone().unwrap(), m.clone()));
+        Attractor {
+            name,
+            position: config.position.unwrap(),
+            mover,
+            strength: Param::from_config(&config.strength.unwrap()),
+            falloff: config.falloff.unwrap(),
+            radius: config.radius,
+            sign: if config.repel.unwrap() { -1.0 } else { 1.0 },
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn position(&self) -> Point2 {
+        self.position
+    }
+
+    /// Moves the attractor and re-evaluates its strength for sketch time `t`.
+    pub fn update(&mut self, t: f32, frame: u64) {
+        self.strength.update(t, frame);
+        if let Some(ref mut m) = self.mover {
+            m.update(t, frame);
+            self.position = m.get_postion(t);
+        }
+    }
+
+    pub fn state(&self) -> AttractorState {
+        AttractorState {
+            name: self.name.clone(),
+            mover: self.mover.as_ref().map(Mover::state),
+        }
+    }
+
+    pub fn restore(&mut self, state: AttractorState) {
+        if let (Some(mover), Some(state)) = (self.mover.as_mut(), state.mover) {
+            mover.restore(state);
+        }
+    }
+
+    pub fn pull(&self) -> Pull {
+        Pull {
+            position: self.position,
+            strength: self.sign * self.strength.value() as f32,
+            falloff: self.falloff,
+            radius: self.radius.unwrap_or(f32::INFINITY),
+        }
+    }
+}
+
+impl Pull {
+    /// Acceleration, in pixels per tick per tick, of a particle at `position`.
+    pub fn acceleration(&self, position: Vec2) -> Vec2 {
+        let offset = self.position - position;
+        let distance = offset.length();
+        if distance == 0.0 || distance > self.radius {
+            return vec2(0.0, 0.0);
+        }
+        let scale = REFERENCE_DISTANCE / distance.max(MIN_DISTANCE);
+        offset / distance * self.strength * scale.powf(self.falloff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{parse_config, Animated};
+
+    fn attractor(config: AttractorConfig) -> Attractor {
+        Attractor::from_config("a".to_string(), config, &HashMap::new())
+    }
+
+    #[test]
+    fn test_pull() {
+        let pull = attractor(AttractorConfig {
+            position: Some(pt2(100.0, 0.0)),
+            strength: Some(Animated::Constant(0.5)),
+            radius: Some(300.0),
+            ..Default::default()
+        })
+        .pull();
+        // Gravity-like by default: a quarter of the pull at twice the distance.
+        assert_eq!(pull.acceleration(pt2(0.0, 0.0)), vec2(0.5, 0.0));
+        assert_eq!(pull.acceleration(pt2(100.0, 200.0)), vec2(0.0, -0.125));
+        assert_eq!(pull.acceleration(pt2(100.0, 1.0)), vec2(0.0, -50.0));
+        assert_eq!(pull.acceleration(pt2(100.0, 0.0)), vec2(0.0, 0.0));
+        assert_eq!(pull.acceleration(pt2(-201.0, 0.0)), vec2(0.0, 0.0));
+
+        let push = attractor(AttractorConfig {
+            strength: Some(Animated::Constant(0.5)),
+            falloff: Some(0.0),
+            repel: Some(true),
+            ..Default::default()
+        })
+        .pull();
+        assert_eq!(push.acceleration(pt2(0.0, 1000.0)), vec2(0.0, 0.5));
+    }
+
+    #[test]
+    fn test_moving_attractor() {
+        let config = parse_config(
+            r#"
+[movers.circle]
+mover_type = "p_elipse"
+inner = [1, 1]
+outer = [1, 1]
+scale = [100, 100]
+[attractors.a]
+mover = "circle"
+strength = "t"
+"#,
+        )
+        .unwrap()
+        .with_defaults();
+        let mut a = Attractor::from_config(
+            "a".to_string(),
+            config.attractors.unwrap()["a"].clone(),
+            config.movers.as_ref().unwrap(),
+        );
+        a.update(0.0, 0);
+        let start = a.position();
+        assert_eq!(a.pull().strength, 0.0);
+        a.update(0.25, 15);
+        assert_ne!(a.position(), start);
+        assert_eq!(a.pull().strength, 0.25);
+    }
+}
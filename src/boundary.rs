@@ -0,0 +1,194 @@
+use super::config::EmitterConfig;
+use super::emitter::Bounds;
+use super::particle::ParticlePool;
+
+use nannou::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// What happens to particles that reach the edge of the window.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundaryMode {
+    /// Nothing: particles carry on off screen until their life span runs out.
+    #[default]
+    Open,
+    /// Particles die on leaving.
+    Kill,
+    /// Particles leaving one edge come back in at the opposite one.
+    Wrap,
+    /// Particles bounce off the edges, keeping `restitution` of their speed.
+    Bounce,
+    /// Particles stop at the edges and slide along them.
+    Clamp,
+}
+
+/// An emitter's edges: the window's bounds pushed out by a margin, and what
+/// they do to particles.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Boundary {
+    pub mode: BoundaryMode,
+    pub left: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub top: f32,
+    pub restitution: f32,
+}
+
+impl Boundary {
+    /// `config` must have been through `with_defaults`.
+    pub fn from_config(config: &EmitterConfig, bounds: Bounds) -> Self {
+        let margin = config.boundary_margin.unwrap();
+        Boundary {
+            mode: config.boundary_mode.unwrap(),
+            left: bounds.left - margin,
+            right: bounds.right + margin,
+            bottom: bounds.bottom - margin,
+            top: bounds.top + margin,
+            restitution: config.restitution.unwrap(),
+        }
+    }
+
+    /// Applies the boundary to every particle in `pool`, returning how many
+    /// were killed.
+    pub fn apply(&self, pool: &mut ParticlePool) -> usize {
+        match self.mode {
+            BoundaryMode::Open => 0,
+            BoundaryMode::Kill => pool.retain_mut(|p, _| self.contains(*p)),
+            BoundaryMode::Wrap => pool.retain_mut(|p, _| {
+                p.x = wrap(p.x, self.left, self.right);
+                p.y = wrap(p.y, self.bottom, self.top);
+                true
+            }),
+            BoundaryMode::Bounce => pool.retain_mut(|p, v| {
+                bounce(&mut p.x, &mut v.x, self.left, self.right, self.restitution);
+                bounce(&mut p.y, &mut v.y, self.bottom, self.top, self.restitution);
+                true
+            }),
+            BoundaryMode::Clamp => pool.retain_mut(|p, v| {
+                clamp(&mut p.x, &mut v.x, self.left, self.right);
+                clamp(&mut p.y, &mut v.y, self.bottom, self.top);
+                true
+            }),
+        }
+    }
+
+    pub fn contains(&self, p: Vec2) -> bool {
+        (self.left..=self.right).contains(&p.x) && (self.bottom..=self.top).contains(&p.y)
+    }
+}
+
+fn wrap(x: f32, low: f32, high: f32) -> f32 {
+    if x < low || x > high {
+        low + (x - low).rem_euclid(high - low)
+    } else {
+        x
+    }
+}
+
+/// Reflects `x` back inside `[low, high]`, reversing and damping `v` if it was
+/// heading out.
+fn bounce(x: &mut f32, v: &mut f32, low: f32, high: f32, restitution: f32) {
+    if *x < low {
+        *x = (2.0 * low - *x).min(high);
+        *v = v.abs() * restitution;
+    } else if *x > high {
+        *x = (2.0 * high - *x).max(low);
+        *v = -v.abs() * restitution;
+    }
+}
+
+fn clamp(x: &mut f32, v: &mut f32, low: f32, high: f32) {
+    if *x < low {
+        *x = low;
+        *v = v.max(0.0);
+    } else if *x > high {
+        *x = high;
+        *v = v.min(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particle::Particle;
+
+    fn boundary(mode: BoundaryMode) -> Boundary {
+        let config = EmitterConfig {
+            boundary_mode: Some(mode),
+            boundary_margin: Some(10.0),
+            restitution: Some(0.5),
+            ..EmitterConfig::default()
+        };
+        let bounds = Bounds {
+            top: 100.0,
+            bottom: -100.0,
+            left: -200.0,
+            right: 200.0,
+        };
+        Boundary::from_config(&config.with_defaults(), bounds)
+    }
+
+    /// A pool of particles at `positions`, all moving up and to the right.
+    fn pool(positions: &[(f32, f32)]) -> ParticlePool {
+        let mut pool = ParticlePool::new();
+        for (x, y) in positions {
+            pool.push(Particle::new(
+                pt2(*x, *y),
+                vec2(2.0, 4.0),
+                hsla(0.0, 0.5, 0.5, 1.0),
+                1.0,
+                1.0,
+                100.0,
+            ));
+        }
+        pool
+    }
+
+    fn positions(pool: &ParticlePool) -> Vec<(f32, f32)> {
+        pool.positions().iter().map(|p| (p.x, p.y)).collect()
+    }
+
+    #[test]
+    fn test_open_and_kill() {
+        let inside_margin = [(0.0, 0.0), (205.0, 105.0), (-210.0, -110.0)];
+        let outside = [(211.0, 0.0), (0.0, -111.0)];
+        let mut particles = pool(&[&inside_margin[..], &outside[..]].concat());
+        assert_eq!(boundary(BoundaryMode::Open).apply(&mut particles), 0);
+        assert_eq!(particles.len(), 5);
+        assert_eq!(boundary(BoundaryMode::Kill).apply(&mut particles), 2);
+        let mut kept = positions(&particles);
+        kept.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(kept, vec![(-210.0, -110.0), (0.0, 0.0), (205.0, 105.0)]);
+    }
+
+    #[test]
+    fn test_wrap_bounce_clamp() {
+        let start = [(215.0, 0.0), (0.0, -130.0), (-1000.0, 0.0)];
+
+        let mut particles = pool(&start);
+        boundary(BoundaryMode::Wrap).apply(&mut particles);
+        assert_eq!(
+            positions(&particles),
+            vec![(-205.0, 0.0), (0.0, 90.0), (-160.0, 0.0)]
+        );
+
+        let mut particles = pool(&start);
+        boundary(BoundaryMode::Bounce).apply(&mut particles);
+        assert_eq!(
+            positions(&particles),
+            vec![(205.0, 0.0), (0.0, -90.0), (210.0, 0.0)]
+        );
+        let velocities: Vec<Vec2> = particles.velocities().to_vec();
+        assert_eq!(velocities[0], vec2(-1.0, 4.0));
+        assert_eq!(velocities[1], vec2(2.0, 2.0));
+
+        let mut particles = pool(&start);
+        assert_eq!(boundary(BoundaryMode::Clamp).apply(&mut particles), 0);
+        assert_eq!(
+            positions(&particles),
+            vec![(210.0, 0.0), (0.0, -110.0), (-210.0, 0.0)]
+        );
+        assert_eq!(particles.velocities()[0], vec2(0.0, 4.0));
+        assert_eq!(particles.velocities()[1], vec2(2.0, 4.0));
+    }
+}
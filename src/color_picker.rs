@@ -1,5 +1,8 @@
 use super::config::ColorPickerConfig;
+use super::rng::Rng;
+use super::snapshot::Color;
 use nannou::prelude::*;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone)]
 pub struct ColorPicker {
@@ -15,6 +18,15 @@ pub struct ColorPicker {
     pub num_colors: usize,
     colors: Option<Vec<Hsla>>,
     current_color: usize,
+    rng: Rng,
+}
+
+/// The parts of a `ColorPicker` that change as it runs.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ColorPickerState {
+    pub colors: Option<Vec<Color>>,
+    pub current_color: usize,
+    pub rng: Rng,
 }
 
 impl ColorPicker {
@@ -42,19 +54,21 @@ impl ColorPicker {
             num_colors,
             colors: None,
             current_color: 0,
+            rng: Rng::new(0, 0),
         }
     }
 
     pub fn from_config(name: String, config: ColorPickerConfig) -> Self {
-        let hue = config.hue.unwrap_or(0.0);
-        let sat = config.saturation.unwrap_or(0.5);
-        let light = config.lightness.unwrap_or(0.5);
-        let alpha = config.alpha.unwrap_or(1.0);
+        let config = config.with_defaults();
+        let hue = config.hue.unwrap();
+        let sat = config.saturation.unwrap();
+        let light = config.lightness.unwrap();
+        let alpha = config.alpha.unwrap();
         let range_hue = config.range_hue;
         let range_sat = config.range_saturation;
         let range_light = config.range_lightness;
         let range_alpha = config.range_alpha;
-        let num_colors = config.num_colors.unwrap_or(1);
+        let num_colors = config.num_colors.unwrap();
         ColorPicker {
             name,
             hue,
@@ -68,9 +82,34 @@ impl ColorPicker {
             num_colors,
             colors: None,
             current_color: 0,
+            rng: Rng::new(0, 0),
         }
     }
 
+    /// Draws the picker's random values from `rng`.
+    pub fn with_rng(self, rng: Rng) -> Self {
+        ColorPicker { rng, ..self }
+    }
+
+    pub fn state(&self) -> ColorPickerState {
+        ColorPickerState {
+            colors: self
+                .colors
+                .as_ref()
+                .map(|colors| colors.iter().map(|c| Color::from(*c)).collect()),
+            current_color: self.current_color,
+            rng: self.rng.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, state: ColorPickerState) {
+        self.colors = state
+            .colors
+            .map(|colors| colors.into_iter().map(Hsla::from).collect());
+        self.current_color = state.current_color;
+        self.rng = state.rng;
+    }
+
     pub fn get_next_color(&mut self) -> Hsla {
         self.initialize();
         self.current_color += 1;
@@ -89,22 +128,22 @@ impl ColorPicker {
         }
     }
 
-    fn get_colors(&self, n: usize) -> Vec<Hsla> {
+    fn get_colors(&mut self, n: usize) -> Vec<Hsla> {
         let mut colors: Vec<Hsla> = Vec::new();
         let hues = match self.range_hue {
-            Some(range_hue) => gen_values(n, range_hue),
+            Some(range_hue) => gen_values(n, range_hue, &mut self.rng),
             None => vec![self.hue; n],
         };
         let sats = match self.range_sat {
-            Some(range_sat) => gen_values(n, range_sat),
+            Some(range_sat) => gen_values(n, range_sat, &mut self.rng),
             None => vec![self.sat; n],
         };
         let lights = match self.range_light {
-            Some(range_light) => gen_values(n, range_light),
+            Some(range_light) => gen_values(n, range_light, &mut self.rng),
             None => vec![self.light; n],
         };
         let alphas = match self.range_alpha {
-            Some(range_alpha) => gen_values(n, range_alpha),
+            Some(range_alpha) => gen_values(n, range_alpha, &mut self.rng),
             None => vec![self.alpha; n],
         };
         for i in 0..n {
@@ -114,10 +153,10 @@ impl ColorPicker {
     }
 }
 
-fn gen_values(n: usize, range: Vec2) -> Vec<f32> {
+fn gen_values(n: usize, range: Vec2, rng: &mut Rng) -> Vec<f32> {
     let mut values = Vec::new();
     let golden_ratio_conjugate = 0.618033988749895;
-    let mut h = random_f32();
+    let mut h = rng.next_f32();
     for _ in 0..n {
         h += golden_ratio_conjugate;
         h %= 1.0;
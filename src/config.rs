@@ -1,23 +1,51 @@
+use super::boundary::BoundaryMode;
+use super::emission::EmissionMode;
+use super::field::NoiseAlgorithm;
+use super::format::Format;
+use super::inheritance;
+use super::integrator::Integrator;
+use super::migration::{self, CONFIG_VERSION};
+use super::validation::{self, Problem, Report};
 use log::*;
 use nannou::prelude::*;
+use serde::de::IntoDeserializer;
 use serde::*;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use toml;
+use toml_edit::{Item, Table, TableLike};
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct Config {
+    pub version: Option<i64>,
+    /// Treat unknown keys as errors instead of warnings.
+    pub strict: Option<bool>,
     pub capture_prefix: Option<String>,
     pub seed: Option<u32>,
+    /// Seconds of simulated time per step, whatever the frame rate.
+    pub dt: Option<f32>,
+    pub integrator: Option<Integrator>,
     pub selected_emitters: Option<Vec<String>>,
     pub window_height: Option<f32>,
     pub window_width: Option<f32>,
+    /// Gravity, wind and drag on every particle, unless a scene or emitter
+    /// sets its own.
+    pub forces: Option<ForcesConfig>,
+    #[serde(serialize_with = "sorted_map")]
     pub emitters: Option<HashMap<String, EmitterConfig>>,
+    #[serde(serialize_with = "sorted_map")]
     pub color_pickers: Option<HashMap<String, ColorPickerConfig>>,
+    #[serde(serialize_with = "sorted_map")]
     pub movers: Option<HashMap<String, MoverConfig>>,
+    #[serde(serialize_with = "sorted_map")]
+    pub attractors: Option<HashMap<String, AttractorConfig>>,
+    /// Named sets of emitters; when present they replace `selected_emitters`.
+    #[serde(serialize_with = "sorted_map")]
+    pub scenes: Option<HashMap<String, SceneConfig>>,
+    pub sequence: Option<SequenceConfig>,
+    pub sweep: Option<SweepConfig>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct ColorPickerConfig {
     pub hue: Option<f32>,
     pub saturation: Option<f32>,
@@ -30,42 +58,452 @@ pub struct ColorPickerConfig {
     pub num_colors: Option<usize>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
 pub struct EmitterConfig {
+    /// Attractors acting on this emitter's particles. Every attractor by default.
+    pub attractors: Option<Vec<String>>,
+    /// What particles do at the edges of the window.
+    pub boundary_mode: Option<BoundaryMode>,
+    /// Pixels the edges sit outside the window; negative moves them in.
+    pub boundary_margin: Option<f32>,
     pub color_picker: Option<String>,
     pub mover: Option<String>,
-    pub flight_size: Option<usize>,
+    pub emission: Option<EmissionConfig>,
+    /// Overrides the scene's and top-level forces, field by field.
+    pub forces: Option<ForcesConfig>,
+    pub flight_size: Option<Animated>,
+    /// Also accepted as `velocity`.
+    #[serde(alias = "velocity")]
     pub initial_velocity: Option<Vec2>,
     pub life_span: Option<f32>,
+    /// Which noise the flow field is made of. Perlin by default.
+    pub noise: Option<NoiseConfig>,
+    /// Strength of the curl of the noise, which swirls particles along its
+    /// contours instead of bunching them up where the angle field converges.
+    /// Adds to `noise_strength`'s angle field.
+    pub noise_curl: Option<Animated>,
     pub noise_field: Option<bool>,
-    pub noise_scale: Option<f64>,
-    pub noise_strength: Option<f32>,
+    pub noise_scale: Option<Animated>,
+    pub noise_strength: Option<Animated>,
+    /// How fast the flow field evolves, in noise units per tick. 0 keeps it
+    /// still.
+    pub noise_time_scale: Option<f32>,
+    /// Pixels per tick the flow field scrolls by.
+    pub noise_drift: Option<Vec2>,
+    /// Where particles spawn when there's no mover. Also accepted as `position`.
+    #[serde(alias = "position")]
     pub origin: Option<Point2>,
-    pub radius: Option<f32>,
+    pub radius: Option<Animated>,
     pub randomize_position: Option<bool>,
     pub randomize_velocity: Option<bool>,
-    pub stroke_weight: Option<f32>,
-    pub velocity: Option<Vec2>,
+    /// Fraction of their speed particles keep when they `bounce`.
+    pub restitution: Option<f32>,
+    /// Seeds this emitter's noise field and random draws in place of the
+    /// top-level `seed`.
+    pub seed: Option<u32>,
+    pub stroke_weight: Option<Animated>,
     pub visualize_noise_field: Option<bool>,
+    /// Swirls acting on this emitter's particles, as `[[emitters.x.vortices]]`.
+    pub vortices: Option<Vec<VortexConfig>>,
+}
+
+/// When an emitter releases particles. Times are in seconds.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+pub struct EmissionConfig {
+    pub mode: Option<EmissionMode>,
+    /// Particles per second, for `steady` and `poisson`.
+    pub rate: Option<Animated>,
+    /// Seconds between flights, for `burst`.
+    pub period: Option<f32>,
+    pub start: Option<f32>,
+    pub stop: Option<f32>,
+    /// Live particles beyond which the emitter holds off.
+    pub max_particles: Option<usize>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
-pub struct MoverConfig  {
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct MoverConfig {
     pub mover_type: String,
     pub inner: Vec2,
     pub outer: Vec2,
     pub scale: Vec2,
     pub translation: Option<Vec2>,
-    pub rotation_angle: Option<f32>,
-    pub rotation_speed: Option<f32>,
+    pub rotation_angle: Option<Animated>,
+    pub rotation_speed: Option<Animated>,
+}
+
+/// A point that pulls particles towards it, or with `repel` pushes them away.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+pub struct AttractorConfig {
+    /// Where the attractor sits when it has no mover.
+    pub position: Option<Point2>,
+    pub mover: Option<String>,
+    /// Pull on a particle 100 pixels away, in pixels per tick per tick.
+    pub strength: Option<Animated>,
+    /// How fast the pull weakens with distance: 2 is like gravity, 0 not at all.
+    pub falloff: Option<f32>,
+    /// Pixels beyond which the attractor has no effect. Unlimited by default.
+    pub radius: Option<f32>,
+    pub repel: Option<bool>,
+}
+
+/// A point particles circle around, counter-clockwise for positive strengths.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+pub struct VortexConfig {
+    pub center: Option<Point2>,
+    /// Push along the circle 100 pixels out, in pixels per tick per tick.
+    pub strength: Option<f32>,
+    /// How fast the swirl weakens with distance: 1 is like a free vortex, 0
+    /// not at all.
+    pub falloff: Option<f32>,
+    /// Pixels beyond which the vortex has no effect. Unlimited by default.
+    pub radius: Option<f32>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct SceneConfig {
+    pub emitters: Vec<String>,
+    /// Steps of `dt` to show the scene before moving on, however many frames
+    /// are drawn meanwhile. Without one the scene stays until the next-scene
+    /// key is pressed.
+    pub duration: Option<u64>,
+    /// Overrides the top-level forces, field by field, while the scene shows.
+    pub forces: Option<ForcesConfig>,
+}
+
+/// Forces from the environment. Accelerations are in pixels per tick per tick.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ForcesConfig {
+    pub gravity: Option<Vec2>,
+    /// A steady push.
+    pub wind: Option<Vec2>,
+    /// Strength of gusts on top of the wind, varying over space and time.
+    pub turbulence: Option<f32>,
+    /// Noise per pixel for the gusts; smaller gives broader gusts.
+    pub turbulence_scale: Option<f32>,
+    /// Drag in proportion to speed, as a fraction of velocity lost per tick.
+    pub linear_drag: Option<f32>,
+    /// Drag in proportion to speed squared.
+    pub quadratic_drag: Option<f32>,
+}
+
+/// The noise behind a flow field. `frequency` multiplies `noise_scale`.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+pub struct NoiseConfig {
+    pub algorithm: Option<NoiseAlgorithm>,
+    /// Layers of detail, for `fbm`, `billow` and `ridged_multi`.
+    pub octaves: Option<usize>,
+    pub frequency: Option<f32>,
+    /// Frequency of each octave relative to the one before.
+    pub lacunarity: Option<f32>,
+    /// Amplitude of each octave relative to the one before.
+    pub persistence: Option<f32>,
+    /// How far, in noise units, the input is pushed around by a second noise
+    /// before sampling. 0 leaves the field unwarped.
+    pub warp: Option<f32>,
+    pub warp_frequency: Option<f32>,
+}
+
+/// The order `scenes` play in and how they blend into each other.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct SequenceConfig {
+    /// Scene names, in order. Defaults to every scene, sorted by name.
+    pub scenes: Option<Vec<String>>,
+    /// Steps of `dt` emitters take to fade in or out when the scene changes.
+    /// Also accepted as `fade_frames`.
+    #[serde(alias = "fade_frames")]
+    pub fade_steps: Option<u64>,
+    /// Start over after the last scene instead of staying on it.
+    pub repeat: Option<bool>,
+}
+
+/// A number, or an expression in `t` and `step` such as `"0.5 + 0.2*sin(t*3)"`
+/// that is re-evaluated every step. `step` counts steps of `dt`, not rendered
+/// frames, so it is the same however fast the sketch draws. See
+/// `expression::Expr`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Animated {
+    Constant(f64),
+    Expression(String),
+}
+
+/// Lists config values to vary; `sweep::expand` turns a config into one variant
+/// per combination.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct SweepConfig {
+    /// Steps of `dt` to run each variant before capturing it.
+    pub frames: Option<u64>,
+    /// Dotted config paths (e.g. `"emitters.rainbow.noise_scale"`) to the values
+    /// each should take.
+    pub values: BTreeMap<String, SweepValues>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum SweepValues {
+    List(Vec<toml::Value>),
+    /// `steps` evenly spaced values from `from` to `to`, inclusive.
+    Range {
+        from: f64,
+        to: f64,
+        steps: usize,
+    },
+}
+
+impl Config {
+    /// Fills every unset field with the value the app would fall back to, so the
+    /// result describes a run completely.
+    pub fn with_defaults(self) -> Self {
+        let has_scenes = self.scenes.as_ref().is_some_and(|s| !s.is_empty());
+        let sequence = match self.sequence {
+            Some(sequence) => Some(sequence.with_defaults(&self.scenes)),
+            None if has_scenes => Some(
+                SequenceConfig {
+                    scenes: None,
+                    fade_steps: None,
+                    repeat: None,
+                }
+                .with_defaults(&self.scenes),
+            ),
+            None => None,
+        };
+        Config {
+            version: Some(self.version.unwrap_or(CONFIG_VERSION)),
+            strict: Some(self.strict.unwrap_or(false)),
+            capture_prefix: self.capture_prefix,
+            seed: Some(self.seed.unwrap_or(0)),
+            dt: Some(self.dt.unwrap_or(1.0 / 60.0)),
+            integrator: Some(self.integrator.unwrap_or_default()),
+            selected_emitters: Some(
+                self.selected_emitters
+                    .unwrap_or_else(|| vec!["default".to_string()]),
+            ),
+            window_height: Some(self.window_height.unwrap_or(600.0)),
+            window_width: Some(self.window_width.unwrap_or(600.0)),
+            forces: Some(self.forces.unwrap_or_default().with_defaults()),
+            emitters: Some(map_values(self.emitters, EmitterConfig::with_defaults)),
+            color_pickers: Some(map_values(
+                self.color_pickers,
+                ColorPickerConfig::with_defaults,
+            )),
+            movers: Some(map_values(self.movers, MoverConfig::with_defaults)),
+            attractors: Some(map_values(self.attractors, AttractorConfig::with_defaults)),
+            sequence,
+            scenes: self.scenes,
+            sweep: self.sweep,
+        }
+    }
+}
+
+impl ColorPickerConfig {
+    pub fn with_defaults(self) -> Self {
+        ColorPickerConfig {
+            hue: Some(self.hue.unwrap_or(0.0)),
+            saturation: Some(self.saturation.unwrap_or(0.5)),
+            lightness: Some(self.lightness.unwrap_or(0.5)),
+            alpha: Some(self.alpha.unwrap_or(1.0)),
+            num_colors: Some(self.num_colors.unwrap_or(1)),
+            ..self
+        }
+    }
+}
+
+impl EmitterConfig {
+    pub fn with_defaults(self) -> Self {
+        EmitterConfig {
+            boundary_mode: Some(self.boundary_mode.unwrap_or_default()),
+            boundary_margin: Some(self.boundary_margin.unwrap_or(0.0)),
+            emission: Some(self.emission.unwrap_or_default().with_defaults()),
+            flight_size: Some(self.flight_size.unwrap_or(Animated::Constant(10.0))),
+            initial_velocity: Some(self.initial_velocity.unwrap_or(vec2(0.0, 0.0))),
+            life_span: Some(self.life_span.unwrap_or(512.0)),
+            noise: Some(self.noise.unwrap_or_default().with_defaults()),
+            noise_curl: Some(self.noise_curl.unwrap_or(Animated::Constant(0.0))),
+            noise_field: Some(self.noise_field.unwrap_or(false)),
+            noise_scale: Some(self.noise_scale.unwrap_or(Animated::Constant(0.0))),
+            noise_strength: Some(self.noise_strength.unwrap_or(Animated::Constant(0.0))),
+            noise_time_scale: Some(self.noise_time_scale.unwrap_or(0.0)),
+            noise_drift: Some(self.noise_drift.unwrap_or(vec2(0.0, 0.0))),
+            origin: Some(self.origin.unwrap_or(pt2(0.0, 0.0))),
+            radius: Some(self.radius.unwrap_or(Animated::Constant(10.0))),
+            randomize_position: Some(self.randomize_position.unwrap_or(false)),
+            randomize_velocity: Some(self.randomize_velocity.unwrap_or(true)),
+            restitution: Some(self.restitution.unwrap_or(1.0)),
+            stroke_weight: Some(self.stroke_weight.unwrap_or(Animated::Constant(2.0))),
+            visualize_noise_field: Some(self.visualize_noise_field.unwrap_or(false)),
+            vortices: self
+                .vortices
+                .map(|v| v.into_iter().map(VortexConfig::with_defaults).collect()),
+            ..self
+        }
+    }
+}
+
+impl EmissionConfig {
+    pub fn with_defaults(self) -> Self {
+        EmissionConfig {
+            mode: Some(self.mode.unwrap_or_default()),
+            rate: Some(self.rate.unwrap_or(Animated::Constant(10.0))),
+            period: Some(self.period.unwrap_or(1.0)),
+            start: Some(self.start.unwrap_or(0.0)),
+            ..self
+        }
+    }
+}
+
+impl MoverConfig {
+    pub fn with_defaults(self) -> Self {
+        MoverConfig {
+            translation: Some(self.translation.unwrap_or(vec2(0., 0.))),
+            rotation_angle: Some(self.rotation_angle.unwrap_or(Animated::Constant(0.))),
+            rotation_speed: Some(self.rotation_speed.unwrap_or(Animated::Constant(0.))),
+            ..self
+        }
+    }
+}
+
+impl AttractorConfig {
+    pub fn with_defaults(self) -> Self {
+        AttractorConfig {
+            position: Some(self.position.unwrap_or(pt2(0.0, 0.0))),
+            strength: Some(self.strength.unwrap_or(Animated::Constant(0.05))),
+            falloff: Some(self.falloff.unwrap_or(2.0)),
+            repel: Some(self.repel.unwrap_or(false)),
+            ..self
+        }
+    }
+}
+
+impl NoiseConfig {
+    pub fn with_defaults(self) -> Self {
+        NoiseConfig {
+            algorithm: Some(self.algorithm.unwrap_or_default()),
+            octaves: Some(self.octaves.unwrap_or(6)),
+            frequency: Some(self.frequency.unwrap_or(1.0)),
+            lacunarity: Some(self.lacunarity.unwrap_or(2.0)),
+            persistence: Some(self.persistence.unwrap_or(0.5)),
+            warp: Some(self.warp.unwrap_or(0.0)),
+            warp_frequency: Some(self.warp_frequency.unwrap_or(1.0)),
+        }
+    }
+}
+
+impl VortexConfig {
+    pub fn with_defaults(self) -> Self {
+        VortexConfig {
+            center: Some(self.center.unwrap_or(pt2(0.0, 0.0))),
+            strength: Some(self.strength.unwrap_or(0.05)),
+            falloff: Some(self.falloff.unwrap_or(1.0)),
+            ..self
+        }
+    }
+}
+
+impl ForcesConfig {
+    pub fn with_defaults(self) -> Self {
+        ForcesConfig {
+            gravity: Some(self.gravity.unwrap_or(vec2(0.0, 0.0))),
+            wind: Some(self.wind.unwrap_or(vec2(0.0, 0.0))),
+            turbulence: Some(self.turbulence.unwrap_or(0.0)),
+            turbulence_scale: Some(self.turbulence_scale.unwrap_or(0.01)),
+            linear_drag: Some(self.linear_drag.unwrap_or(0.0)),
+            quadratic_drag: Some(self.quadratic_drag.unwrap_or(0.0)),
+        }
+    }
+
+    /// This config, with the fields it leaves unset taken from `fallback`.
+    pub fn or(self, fallback: &ForcesConfig) -> Self {
+        ForcesConfig {
+            gravity: self.gravity.or(fallback.gravity),
+            wind: self.wind.or(fallback.wind),
+            turbulence: self.turbulence.or(fallback.turbulence),
+            turbulence_scale: self.turbulence_scale.or(fallback.turbulence_scale),
+            linear_drag: self.linear_drag.or(fallback.linear_drag),
+            quadratic_drag: self.quadratic_drag.or(fallback.quadratic_drag),
+        }
+    }
+}
+
+impl SequenceConfig {
+    pub fn with_defaults(self, scenes: &Option<HashMap<String, SceneConfig>>) -> Self {
+        SequenceConfig {
+            scenes: Some(self.scenes.unwrap_or_else(|| {
+                let mut names: Vec<String> =
+                    scenes.iter().flat_map(|s| s.keys().cloned()).collect();
+                names.sort();
+                names
+            })),
+            fade_steps: Some(self.fade_steps.unwrap_or(60)),
+            repeat: Some(self.repeat.unwrap_or(true)),
+        }
+    }
+}
+
+fn map_values<T>(map: Option<HashMap<String, T>>, f: fn(T) -> T) -> HashMap<String, T> {
+    map.unwrap_or_default()
+        .into_iter()
+        .map(|(k, v)| (k, f(v)))
+        .collect()
+}
+
+fn sorted_map<S: Serializer, T: Serialize>(
+    map: &Option<HashMap<String, T>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match map {
+        Some(map) => serializer.collect_map(map.iter().collect::<BTreeMap<_, _>>()),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Renders a config as TOML that `parse_config` reads back to the same value.
+pub fn to_toml(config: &Config) -> Result<String, toml::ser::Error> {
+    let mut value = toml::Value::try_from(config)?;
+    shorten_floats(&mut value);
+    toml::to_string(&value)
+}
+
+/// Most fields are `f32`, which widen to noisy `f64`s (0.005 -> 0.004999999888241291).
+/// Print those with the shortest representation that reads back to the same `f32`.
+fn shorten_floats(value: &mut toml::Value) {
+    match value {
+        toml::Value::Float(f) => {
+            let narrow = *f as f32;
+            if narrow as f64 == *f {
+                *f = narrow.to_string().parse().unwrap_or(*f);
+            }
+        }
+        toml::Value::Array(values) => values.iter_mut().for_each(shorten_floats),
+        toml::Value::Table(table) => table.iter_mut().for_each(|(_, v)| shorten_floats(v)),
+        _ => {}
+    }
+}
+
+/// Reads and validates a config file, falling back to a built-in default config
+/// when the file can't be read. The file is read as `format`, or else as the format
+/// its extension implies, or TOML. Warnings are logged; any error fails the whole read.
+pub fn read_config(
+    filename: &str,
+    format: Option<Format>,
+    overrides: &[String],
+) -> Result<Config, Report> {
+    let (contents, format) = read_source(filename, format);
+    parse_config_as(&contents, format, overrides)
 }
 
-pub fn read_config(filename: &str) -> Config {
-    let contents = match fs::read_to_string(filename) {
-        Ok(contents) => contents,
+/// The text and format of a config file, or the built-in default config if it
+/// can't be read.
+pub fn read_source(filename: &str, format: Option<Format>) -> (String, Format) {
+    match fs::read_to_string(filename) {
+        Ok(contents) => {
+            let format = format.or_else(|| Format::from_path(filename));
+            (contents, format.unwrap_or(Format::Toml))
+        }
         Err(e) => {
             info!("Error reading file: {}", e);
-            r#"
+            let contents = r#"
+            version = 2
             capture_prefix = "particle_"
 
             [color_pickers]
@@ -76,22 +514,212 @@ pub fn read_config(filename: &str) -> Config {
             
             [emitters]
             [emitters.default]
-                position = [0, 0]
+                origin = [0, 0]
                 velocity = [0, 0]
                 life_span = 512
                 randomize_position = false
                 color_picker = "mono_green"
-            "#
-            .to_string()
+            "#;
+            (contents.to_string(), Format::Toml)
+        }
+    }
+}
+
+pub fn parse_config(contents: &str) -> Result<Config, Report> {
+    parse_config_as(contents, Format::Toml, &[])
+}
+
+pub fn parse_config_as(
+    contents: &str,
+    format: Format,
+    overrides: &[String],
+) -> Result<Config, Report> {
+    let (root, report) = parse_table(contents, format, overrides)?;
+    config_from_table(root, contents, report)
+}
+
+/// Parses `contents`, applies `overrides` and then migrations and inheritance,
+/// giving the table a `Config` is deserialized from along with any warnings so far.
+/// Overrides go in first so that they reach entries that inherit what they set,
+/// and can set `defaults.*` themselves.
+pub fn parse_table(
+    contents: &str,
+    format: Format,
+    overrides: &[String],
+) -> Result<(Table, Report), Report> {
+    let mut report = Report::default();
+    let mut root = format.parse(contents)?;
+    apply_overrides(&mut root, overrides)?;
+    migration::migrate(&mut root, contents, &mut report);
+    inheritance::resolve(&mut root, contents, &mut report);
+    if report.has_errors() {
+        return Err(report);
+    }
+    check_override_keys(&root, overrides)?;
+    Ok((root, report))
+}
+
+/// Applies `path=value` overrides, as passed to `--set`, to a parsed table. Values
+/// are read as TOML (`6`, `[1, 2]`, `true`); anything that isn't valid TOML is
+/// taken as a string, so `--set capture_prefix=run_` needs no quotes. They are
+/// type checked with the rest of the table when it's deserialized.
+pub fn apply_overrides(root: &mut Table, overrides: &[String]) -> Result<(), Report> {
+    let mut report = Report::default();
+    for o in overrides {
+        let (path, value) = match o.split_once('=') {
+            Some((path, value)) => (path.trim(), value.trim()),
+            None => {
+                let problem = Problem::Parse(format!("expected path=value, got {:?}", o));
+                report.extend(Report::error(problem, ""));
+                continue;
+            }
+        };
+        let value = value
+            .parse()
+            .unwrap_or_else(|_| toml_edit::Value::from(value));
+        if let Err(problem) = set_path(root, path, value) {
+            report.extend(Report::error(problem, path));
         }
+    }
+    if report.has_errors() {
+        return Err(report);
+    }
+    Ok(())
+}
+
+/// Fails if any of `overrides` set a key the schema doesn't have, `strict` or
+/// not: a typo on the command line would otherwise leave the value it meant to
+/// change untouched.
+fn check_override_keys(root: &Table, overrides: &[String]) -> Result<(), Report> {
+    if overrides.is_empty() {
+        return Ok(());
+    }
+    // Type errors are left to `config_from_table`, which reports them in full.
+    let unknown_keys = match deserialize(root.clone()) {
+        Ok((_, unknown_keys)) => unknown_keys,
+        Err(_) => return Ok(()),
     };
-    let config: Config = toml::from_str(&contents).unwrap();
-    config
+    let mut report = Report::default();
+    for o in overrides {
+        let path = match o.split_once('=') {
+            Some((path, _)) => path.trim(),
+            None => continue,
+        };
+        let segments: Vec<&str> = path.split('.').collect();
+        if unknown_keys.iter().any(|key| sets_key(&segments, key)) {
+            let problem = Problem::UnknownKey(segments.last().unwrap().to_string());
+            report.extend(Report::error(problem, path));
+        }
+    }
+    if report.has_errors() {
+        return Err(report);
+    }
+    Ok(())
+}
+
+/// Whether an override of `path` put `key` in the resolved table, directly or
+/// through the entries inheriting a `[defaults.<kind>]` table.
+fn sets_key(path: &[&str], key: &[String]) -> bool {
+    let key = key.iter().map(String::as_str);
+    match path {
+        ["defaults", kind, ..] if path.len() > 2 => match inheritance::section(kind) {
+            Some(section) => {
+                key.len() == path.len()
+                    && key.clone().next() == Some(section)
+                    && key.skip(2).eq(path[2..].iter().copied())
+            }
+            None => false,
+        },
+        _ => key.eq(path.iter().copied()),
+    }
+}
+
+/// Deserializes and validates a table from `parse_table`, logging warnings.
+pub fn config_from_table(
+    root: Table,
+    contents: &str,
+    mut report: Report,
+) -> Result<Config, Report> {
+    match deserialize(root.clone()) {
+        Ok((config, unknown_keys)) => {
+            report.extend(validation::validate(
+                &config,
+                &root,
+                contents,
+                &unknown_keys,
+            ));
+            if !report.has_errors() {
+                for w in report.warnings() {
+                    warn!("{}", w);
+                }
+                return Ok(config);
+            }
+        }
+        Err(e) => {
+            let mut error = Report::parse_error(e.message(), e.span(), contents);
+            // Values without a span, like overrides, are only identified by their key.
+            let path = e
+                .to_string()
+                .lines()
+                .find_map(|line| Some(line.strip_prefix("in `")?.strip_suffix('`')?.to_string()));
+            error.diagnostics[0].path = path.unwrap_or_default();
+            report.extend(error);
+        }
+    }
+    Err(report)
+}
+
+/// Sets the value at a dotted `path`, replacing or adding its last segment. Every
+/// segment before it must already be a table.
+pub fn set_path(root: &mut Table, path: &str, value: toml_edit::Value) -> Result<(), Problem> {
+    let segments: Vec<&str> = path.split('.').collect();
+    let (last, parents) = segments.split_last().unwrap();
+    let mut table: &mut dyn TableLike = root;
+    for segment in parents {
+        table = match table.get_mut(segment).and_then(Item::as_table_like_mut) {
+            Some(table) => table,
+            None => return Err(Problem::UnknownPath(path.to_string())),
+        };
+    }
+    table.insert(last, Item::Value(value));
+    Ok(())
+}
+
+/// Deserializes a `Config` from a parsed TOML table, collecting the paths of keys
+/// that aren't part of the schema instead of silently dropping them.
+pub fn deserialize(root: Table) -> Result<(Config, Vec<Vec<String>>), toml_edit::de::Error> {
+    let mut unknown_keys = Vec::new();
+    let deserializer = toml_edit::Value::InlineTable(root.into_inline_table()).into_deserializer();
+    let config =
+        serde_ignored::deserialize(deserializer, |path| unknown_keys.push(path_segments(&path)))?;
+    Ok((config, unknown_keys))
+}
+
+fn path_segments(path: &serde_ignored::Path) -> Vec<String> {
+    use serde_ignored::Path;
+    match path {
+        Path::Root => Vec::new(),
+        Path::Seq { parent, index } => {
+            let mut segments = path_segments(parent);
+            segments.push(index.to_string());
+            segments
+        }
+        Path::Map { parent, key } => {
+            let mut segments = path_segments(parent);
+            segments.push(key.clone());
+            segments
+        }
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => path_segments(parent),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use toml_edit::ImDocument;
+
     #[test]
     fn test_basic() {
         const TEXT: &str = r#"
@@ -119,12 +747,17 @@ mod tests {
             some_bullshit = 0.7
         "#;
 
-        let config: Config = toml::from_str(TEXT).unwrap();
+        let root = ImDocument::parse(TEXT).unwrap().into_table();
+        let (config, unknown_keys) = deserialize(root).unwrap();
         println!("{:#?}", config);
         assert_eq!(
             config.color_pickers.unwrap()["mono_green"].hue.unwrap(),
             120.0
         );
+        assert_eq!(
+            unknown_keys,
+            vec![vec!["color_pickers", "mono_green", "some_bullshit"]]
+        );
     }
 
     #[test]
@@ -149,9 +782,202 @@ mod tests {
 
         fs::write("/tmp/conf", TEXT).expect("Unable to write file");
 
-        let config: Config = read_config("/tmp/conf");
+        let config: Config = read_config("/tmp/conf", None, &[]).unwrap();
         println!("{:#?}", config);
         assert!(config.color_pickers.unwrap().contains_key("mono_green"));
         // assert_eq!(config.color_pickers.unwrap()["mono_green"].range_saturation.unwrap(), vec2(0.3, 0.7));
     }
+
+    #[test]
+    fn test_read_default() {
+        let config: Config = read_config("/nonexistent/config.toml", None, &[]).unwrap();
+        assert!(config.emitters.unwrap().contains_key("default"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        const TEXT: &str = r#"
+        selected_emitters = ["emitter_1", "typo"]
+
+        [emitters]
+          [emitters.emitter_1]
+            life_span = "long"
+        "#;
+        let report = parse_config(TEXT).unwrap_err();
+        assert_eq!(report.errors().count(), 1);
+        assert_eq!(report.diagnostics[0].location.unwrap().line, 6);
+
+        let report = parse_config(&TEXT.replace("\"long\"", "512")).unwrap_err();
+        assert_eq!(
+            report.diagnostics[0].problem,
+            validation::Problem::UnknownEmitter("typo".to_string())
+        );
+    }
+
+    #[test]
+    fn test_extends() {
+        const TEXT: &str = r#"
+        version = 2
+        selected_emitters = ["child"]
+        [defaults.emitter]
+          life_span = 100
+        [emitters.base]
+          color_picker = "green"
+          radius = 4
+        [emitters.child]
+          extends = "base"
+          radius = 2
+        [color_pickers.green]
+          hue = 120
+        [color_pickers.dark_green]
+          extends = "green"
+          lightness = 1.5
+        "#;
+        let report = parse_config(TEXT).unwrap_err();
+        // The inherited color picker is validated where the value was written.
+        assert_eq!(report.errors().count(), 1);
+        assert_eq!(report.diagnostics[0].location.unwrap().line, 16);
+
+        let config = parse_config(&TEXT.replace("1.5", "0.2")).unwrap();
+        let child = &config.emitters.unwrap()["child"];
+        assert_eq!(child.color_picker.as_deref(), Some("green"));
+        assert_eq!(child.radius, Some(Animated::Constant(2.0)));
+        assert_eq!(child.life_span, Some(100.0));
+    }
+
+    #[test]
+    fn test_effective_config_round_trips() {
+        const TEXT: &str = r#"
+        selected_emitters = ["e"]
+        [emitters.e]
+          noise_scale = 0.005
+          noise_strength = 0.3
+          color_picker = "c"
+          mover = "m"
+        [color_pickers.c]
+          range_hue = [0, 360]
+        [movers.m]
+          mover_type = "p_elipse"
+          inner = [1, 3]
+          outer = [1, 1]
+          scale = [800, 800]
+        "#;
+        let config = parse_config(TEXT).unwrap().with_defaults();
+        let e = &config.emitters.as_ref().unwrap()["e"];
+        assert_eq!(e.life_span, Some(512.0));
+        assert_eq!(e.randomize_velocity, Some(true));
+        assert_eq!(
+            config.color_pickers.as_ref().unwrap()["c"].num_colors,
+            Some(1)
+        );
+
+        let text = to_toml(&config).unwrap();
+        assert!(text.contains("noise_strength = 0.3\n"), "{}", text);
+        assert_eq!(parse_config(&text).unwrap(), config);
+    }
+
+    #[test]
+    fn test_overrides() {
+        const TEXT: &str = r#"
+        selected_emitters = ["e"]
+        [emitters.e]
+          radius = 4
+        "#;
+        let set = |overrides: &[&str]| {
+            let overrides: Vec<String> = overrides.iter().map(|o| o.to_string()).collect();
+            parse_config_as(TEXT, Format::Toml, &overrides)
+        };
+        let config = set(&["seed=42", "emitters.e.radius = 6", "capture_prefix=run_"]).unwrap();
+        assert_eq!(config.seed, Some(42));
+        assert_eq!(config.capture_prefix.as_deref(), Some("run_"));
+        assert_eq!(
+            config.emitters.unwrap()["e"].radius,
+            Some(Animated::Constant(6.0))
+        );
+
+        let report = set(&["emitters.f.radius=6", "seed"]).unwrap_err();
+        let errors: Vec<String> = report.errors().map(|d| d.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "error: emitters.f.radius: no config table for \"emitters.f.radius\"",
+                "error: expected path=value, got \"seed\"",
+            ]
+        );
+        let report = set(&["seed=many"]).unwrap_err();
+        assert_eq!(
+            report.to_string(),
+            "error: seed: invalid type: string \"many\", expected u32\n"
+        );
+    }
+
+    #[test]
+    fn test_overrides_are_inherited() {
+        const TEXT: &str = r#"
+        selected_emitters = ["child", "other"]
+        [defaults.emitter]
+          radius = 4
+        [emitters.base]
+          stroke_weight = 1
+        [emitters.child]
+          extends = "base"
+        [emitters.other]
+        "#;
+        let overrides = [
+            "emitters.base.stroke_weight=5".to_string(),
+            "defaults.emitter.radius=9".to_string(),
+        ];
+        let emitters = parse_config_as(TEXT, Format::Toml, &overrides)
+            .unwrap()
+            .emitters
+            .unwrap();
+        assert_eq!(
+            emitters["child"].stroke_weight,
+            Some(Animated::Constant(5.0))
+        );
+        for name in ["base", "child", "other"] {
+            assert_eq!(emitters[name].radius, Some(Animated::Constant(9.0)));
+        }
+        // Nor do typos, whatever `strict` says.
+        for path in ["emitters.child.radus", "defaults.emitter.radus"] {
+            let report = parse_config_as(TEXT, Format::Toml, &[format!("{}=9", path)]).unwrap_err();
+            assert_eq!(
+                report.to_string(),
+                format!("error: {}: unknown key \"radus\"\n", path)
+            );
+        }
+        // Missing tables still aren't made up.
+        let report =
+            parse_config_as(TEXT, Format::Toml, &["defaults.mover.x=1".to_string()]).unwrap_err();
+        assert_eq!(
+            report.to_string(),
+            "error: defaults.mover.x: no config table for \"defaults.mover.x\"\n"
+        );
+    }
+
+    #[test]
+    fn test_position_alias() {
+        const TEXT: &str = r#"
+        version = 2
+        selected_emitters = ["e"]
+        [emitters.e]
+          position = [3, 4]
+        "#;
+        let config = parse_config(TEXT).unwrap();
+        assert_eq!(config.emitters.unwrap()["e"].origin, Some(pt2(3.0, 4.0)));
+    }
+
+    #[test]
+    fn test_velocity_alias() {
+        const TEXT: &str = r#"
+        selected_emitters = ["e"]
+        [emitters.e]
+          velocity = [1, 2]
+        "#;
+        let config = parse_config(TEXT).unwrap();
+        let e = &config.emitters.unwrap()["e"];
+        assert_eq!(e.initial_velocity, Some(vec2(1.0, 2.0)));
+        let both = "[emitters.e]\nvelocity = [1, 2]\ninitial_velocity = [3, 4]\n";
+        assert!(parse_config(both).is_err());
+    }
 }
@@ -0,0 +1,204 @@
+use super::config::EmissionConfig;
+use super::expression::Param;
+use super::rng::Rng;
+use super::world::TICKS_PER_SECOND;
+
+use serde::{Deserialize, Serialize};
+
+/// Chance per tick that a `random` schedule releases a flight of particles.
+pub const EMIT_PROBABILITY: f32 = 0.1;
+
+/// How an emitter decides when to release particles.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmissionMode {
+    /// A flight of `flight_size` particles now and then, at random. What the
+    /// sketch has always done.
+    #[default]
+    Random,
+    /// `rate` particles per second, evenly spread.
+    Steady,
+    /// `rate` particles per second on average, as a Poisson process.
+    Poisson,
+    /// A flight of `flight_size` particles every `period` seconds.
+    Burst,
+}
+
+/// An emitter's emission schedule: how many particles to release each step.
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    mode: EmissionMode,
+    rate: Param,
+    period: f64,
+    start: f64,
+    stop: Option<f64>,
+    max_particles: Option<usize>,
+    // Fractional particles a steady schedule still owes.
+    owed: f64,
+    next_burst: f64,
+    rng: Rng,
+}
+
+/// The parts of a `Schedule` that change as it runs.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ScheduleState {
+    pub owed: f64,
+    pub next_burst: f64,
+    pub rng: Rng,
+}
+
+impl Schedule {
+    /// `config` must have been through `with_defaults`.
+    pub fn from_config(config: &EmissionConfig, rng: Rng) -> Self {
+        let start = config.start.unwrap() as f64;
+        Schedule {
+            mode: config.mode.unwrap(),
+            rate: Param::from_config(config.rate.as_ref().unwrap()),
+            period: config.period.unwrap() as f64,
+            start,
+            stop: config.stop.map(|stop| stop as f64),
+            max_particles: config.max_particles,
+            owed: 0.0,
+            next_burst: start,
+            rng,
+        }
+    }
+
+    pub fn state(&self) -> ScheduleState {
+        ScheduleState {
+            owed: self.owed,
+            next_burst: self.next_burst,
+            rng: self.rng.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, state: ScheduleState) {
+        self.owed = state.owed;
+        self.next_burst = state.next_burst;
+        self.rng = state.rng;
+    }
+
+    /// Re-evaluates the rate for sketch time `t` and `frame`.
+    pub fn update(&mut self, t: f32, frame: u64) {
+        self.rate.update(t, frame);
+    }
+
+    /// How many particles to release over the `dt` seconds starting at `time`,
+    /// given a flight size and the number already alive.
+    pub fn count(&mut self, time: f64, dt: f64, flight_size: usize, live: usize) -> usize {
+        if time < self.start || self.stop.is_some_and(|stop| time >= stop) {
+            return 0;
+        }
+        let rate = self.rate.value().max(0.0);
+        let count = match self.mode {
+            EmissionMode::Random => {
+                let ticks = (dt * TICKS_PER_SECOND) as f32;
+                if self.rng.next_f32() < EMIT_PROBABILITY * ticks {
+                    flight_size
+                } else {
+                    0
+                }
+            }
+            EmissionMode::Steady => {
+                self.owed += rate * dt;
+                let count = self.owed.floor();
+                self.owed -= count;
+                count as usize
+            }
+            EmissionMode::Poisson => poisson(&mut self.rng, rate * dt),
+            EmissionMode::Burst => {
+                if time < self.next_burst {
+                    0
+                } else {
+                    // Skip bursts missed while paused or hidden.
+                    while self.next_burst <= time {
+                        self.next_burst += self.period;
+                    }
+                    flight_size
+                }
+            }
+        };
+        match self.max_particles {
+            Some(max) => count.min(max.saturating_sub(live)),
+            None => count,
+        }
+    }
+}
+
+/// A Poisson-distributed count with the given mean, by Knuth's method. Large
+/// means are split up so that `exp(-mean)` doesn't underflow.
+fn poisson(rng: &mut Rng, mean: f64) -> usize {
+    let mut remaining = mean;
+    let mut count = 0;
+    while remaining > 0.0 {
+        let chunk = remaining.min(30.0);
+        remaining -= chunk;
+        let limit = (-chunk).exp();
+        let mut p = rng.next_f32() as f64;
+        while p > limit {
+            count += 1;
+            p *= rng.next_f32() as f64;
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Animated;
+
+    const DT: f64 = 1.0 / 64.0;
+
+    fn schedule(mode: EmissionMode, rate: f64) -> Schedule {
+        let config = EmissionConfig {
+            mode: Some(mode),
+            rate: Some(Animated::Constant(rate)),
+            period: Some(0.5),
+            start: Some(1.0),
+            stop: Some(3.0),
+            ..Default::default()
+        };
+        Schedule::from_config(&config.with_defaults(), Rng::new(1, 2))
+    }
+
+    /// Particles released each step over four seconds.
+    fn run(schedule: &mut Schedule) -> Vec<usize> {
+        (0..256)
+            .map(|i| schedule.count(i as f64 * DT, DT, 5, 0))
+            .collect()
+    }
+
+    #[test]
+    fn test_steady_and_bursts_between_start_and_stop() {
+        let counts = run(&mut schedule(EmissionMode::Steady, 16.0));
+        assert_eq!(counts.iter().sum::<usize>(), 32);
+        assert!(counts[..64].iter().chain(&counts[192..]).all(|c| *c == 0));
+        assert!(counts[64..192].iter().all(|c| *c <= 1));
+
+        let counts = run(&mut schedule(EmissionMode::Burst, 0.0));
+        let bursts: Vec<usize> = (0..256).filter(|i| counts[*i] > 0).collect();
+        assert_eq!(bursts, vec![64, 96, 128, 160]);
+        assert!(bursts.iter().all(|i| counts[*i] == 5));
+    }
+
+    #[test]
+    fn test_poisson_mean() {
+        let mut schedule = schedule(EmissionMode::Poisson, 100.0);
+        let total: usize = (0..64_000)
+            .map(|i| schedule.count(1.0 + (i % 64) as f64 * DT, DT, 5, 0))
+            .sum();
+        // 1000 seconds at 100 per second.
+        assert!((total as f64 / 100_000.0 - 1.0).abs() < 0.01, "{}", total);
+        assert!(poisson(&mut Rng::new(0, 0), 2000.0) > 1800);
+    }
+
+    #[test]
+    fn test_cap() {
+        let mut schedule = schedule(EmissionMode::Steady, 640.0);
+        assert_eq!(schedule.count(1.0, DT, 5, 95), 10);
+        schedule.max_particles = Some(100);
+        assert_eq!(schedule.count(1.0, DT, 5, 95), 5);
+        assert_eq!(schedule.count(1.0, DT, 5, 120), 0);
+    }
+}
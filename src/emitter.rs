@@ -1,34 +1,66 @@
-use super::color_picker::ColorPicker;
-use super::config::{ColorPickerConfig, EmitterConfig, MoverConfig};
-use super::mover::Mover;
-use super::particle::Particle;
+use super::attractor::{Attractor, Pull};
+use super::boundary::Boundary;
+use super::color_picker::{ColorPicker, ColorPickerState};
+use super::config::{ColorPickerConfig, EmissionConfig, EmitterConfig, ForcesConfig, MoverConfig};
+use super::emission::{Schedule, ScheduleState};
+use super::expression::Param;
+use super::field::{Flow, NoiseField, Vortex};
+use super::forces::Forces;
+use super::integrator::Integrator;
+use super::mover::{Mover, MoverState};
+use super::particle::{Particle, ParticlePool};
+use super::rng::Rng;
+use super::world::{SECONDS_PER_T, TICKS_PER_SECOND};
 
 use log::*;
-use nannou::noise::{NoiseFn, Seedable};
 use nannou::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 #[derive(Debug)]
 pub struct Emitter {
     name: String,
+    // Names of the attractors acting on the particles; `None` for all of them.
+    attractors: Option<Vec<String>>,
     bounds: Bounds,
+    boundary: Boundary,
     color_picker: ColorPicker,
-    flight_size: usize,
+    flight_size: Param,
+    forces: Forces,
+    // The emitter's own forces, which take precedence over its scene's.
+    forces_config: Option<ForcesConfig>,
     initial_velocity: Vec2,
     pub life_span: f32,
     mover: Option<Mover>,
-    noise_field: Option<nannou::noise::Perlin>,
-    noise_scale: f64,
-    noise_strength: f32,
+    noise_field: Option<NoiseField>,
+    noise_scale: Param,
+    noise_strength: Param,
+    noise_curl: Param,
+    // Noise depth and pixels per tick the field evolves and scrolls by.
+    noise_time_scale: f32,
+    noise_drift: Vec2,
+    // Sketch time of the last update, which the drawn field is shown at.
+    t: f32,
+    vortices: Vec<Vortex>,
+    opacity: f32,
+    // Opacity gained or lost per step while fading in or out.
+    fade_step: f32,
     origin: Point2,
-    pub particles: Vec<Particle>,
+    pub particles: ParticlePool,
     paused: bool,
     position: Point2,
-    radius: f32,
+    radius: Param,
     pub randomize_position: bool,
     pub randomize_velocity: bool,
-    stroke_weight: f32,
+    rng: Rng,
+    schedule: Schedule,
+    seed: u32,
+    stroke_weight: Param,
+    visible: bool,
     visualize_noise_field: bool,
+    // Particles released and lost since the last `update` began.
+    births: usize,
+    deaths: usize,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -39,6 +71,25 @@ pub struct Bounds {
     pub right: f32,
 }
 
+/// The parts of an `Emitter` that change as it runs. Everything else is
+/// rebuilt from the config or follows from the clock.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct EmitterState {
+    pub name: String,
+    pub particles: ParticlePool,
+    pub position: Point2,
+    pub opacity: f32,
+    pub fade_step: f32,
+    pub visible: bool,
+    pub paused: bool,
+    pub rng: Rng,
+    pub color_picker: ColorPickerState,
+    pub schedule: ScheduleState,
+    pub mover: Option<MoverState>,
+    /// Sketch time of the last update, which the flow field is drawn at.
+    pub t: f32,
+}
+
 impl Emitter {
     pub fn new(bounds: Bounds) -> Self {
         let color_picker = ColorPicker::new(
@@ -53,29 +104,99 @@ impl Emitter {
             None,
         );
         debug!("color picker: {:#?}", color_picker);
+        let emission = EmissionConfig::default().with_defaults();
+        let schedule = Schedule::from_config(&emission, Rng::new(0, 1));
         Emitter {
             name: "unnamed_emitter".to_string(),
-            particles: Vec::new(),
+            attractors: None,
+            particles: ParticlePool::new(),
             mover: None,
             noise_field: None,
-            noise_scale: 0.0,
-            noise_strength: 0.0,
+            noise_scale: Param::constant(0.0),
+            noise_strength: Param::constant(0.0),
+            noise_curl: Param::constant(0.0),
+            noise_time_scale: 0.0,
+            noise_drift: vec2(0.0, 0.0),
+            t: 0.0,
+            vortices: Vec::new(),
             origin: pt2(0.0, 0.0),
             position: pt2(0.0, 0.0),
             randomize_position: false,
             randomize_velocity: true,
-            flight_size: 10,
+            rng: Rng::new(0, 0),
+            schedule,
+            seed: 0,
+            flight_size: Param::constant(10.0),
+            forces: Forces::from_config(&ForcesConfig::default(), 1),
+            forces_config: None,
             initial_velocity: vec2(0.0, 0.0),
             life_span: 512.0,
-            radius: 10.0,
-            stroke_weight: 2.0,
+            radius: Param::constant(10.0),
+            stroke_weight: Param::constant(2.0),
+            boundary: Boundary::from_config(&EmitterConfig::default().with_defaults(), bounds),
             bounds,
             paused: false,
             color_picker,
+            opacity: 1.0,
+            fade_step: 1.0,
+            visible: true,
             visualize_noise_field: true,
+            births: 0,
+            deaths: 0,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn position(&self) -> Point2 {
+        self.position
+    }
+
+    /// Particles released since the last `update` began.
+    pub fn births(&self) -> usize {
+        self.births
+    }
+
+    /// Particles that died in the last `update`.
+    pub fn deaths(&self) -> usize {
+        self.deaths
+    }
+
+    pub fn state(&self) -> EmitterState {
+        EmitterState {
+            name: self.name.clone(),
+            particles: self.particles.clone(),
+            position: self.position,
+            opacity: self.opacity,
+            fade_step: self.fade_step,
+            visible: self.visible,
+            paused: self.paused,
+            rng: self.rng.clone(),
+            color_picker: self.color_picker.state(),
+            schedule: self.schedule.state(),
+            mover: self.mover.as_ref().map(Mover::state),
+            t: self.t,
         }
     }
 
+    pub fn restore(&mut self, state: EmitterState) {
+        self.particles = state.particles;
+        self.position = state.position;
+        self.opacity = state.opacity;
+        self.fade_step = state.fade_step;
+        self.visible = state.visible;
+        self.paused = state.paused;
+        self.rng = state.rng;
+        self.color_picker.restore(state.color_picker);
+        self.schedule.restore(state.schedule);
+        if let (Some(mover), Some(state)) = (self.mover.as_mut(), state.mover) {
+            mover.restore(state);
+        }
+        self.t = state.t;
+    }
+
     fn color_picker_from_config(
         color_picker_name: &str,
         color_pickers: &HashMap<String, ColorPickerConfig>,
@@ -116,26 +237,44 @@ impl Emitter {
         bounds: Bounds,
         seed: u32,
     ) -> Self {
+        // Every field read below except the color picker and mover names and
+        // the seed is set by `with_defaults`.
+        let config = config.with_defaults();
+        // Each emitter draws from its own stream, so adding or removing one
+        // doesn't change what the others do.
+        let seed = config.seed.unwrap_or(seed);
+        let mut rng = Rng::named(seed as u64, &format!("emitters.{}", name));
+        let boundary = Boundary::from_config(&config, bounds);
+        let attractors = config.attractors.clone();
+        let forces_config = config.forces.clone();
+        // No forces until `set_forces` merges in the scene's.
+        let forces = Forces::from_config(&ForcesConfig::default(), 1);
         let color_picker_name = config.color_picker.unwrap_or("".to_string());
-        let color_picker = Self::color_picker_from_config(&color_picker_name, color_pickers_config);
+        let color_picker = Self::color_picker_from_config(&color_picker_name, color_pickers_config)
+            .with_rng(rng.split());
+        let schedule = Schedule::from_config(config.emission.as_ref().unwrap(), rng.split());
         let mover_name = config.mover.unwrap_or("".to_string());
         let mover = Self::mover_from_config(&mover_name, movers_config);
-        let randomize_position = config.randomize_position.unwrap_or(false);
-        let randomize_velocity = config.randomize_velocity.unwrap_or(true);
-        let initial_velocity = config.initial_velocity.unwrap_or(vec2(0.0, 0.0));
-        let life_span = config.life_span.unwrap_or(512.0);
-        let noise_field_on = config.noise_field.unwrap_or(false);
-        let noise_scale = config.noise_scale.unwrap_or(0.0);
-        let noise_strength = config.noise_strength.unwrap_or(0.0);
-        let origin = config.origin.unwrap_or(pt2(0.0, 0.0));
-        let flight_size = config.flight_size.unwrap_or(10);
-        let radius = config.radius.unwrap_or(10.0);
-        let stroke_weight = config.stroke_weight.unwrap_or(2.0);
-        let visualize_noise_field = config.visualize_noise_field.unwrap_or(false);
+        let randomize_position = config.randomize_position.unwrap();
+        let randomize_velocity = config.randomize_velocity.unwrap();
+        let initial_velocity = config.initial_velocity.unwrap();
+        let life_span = config.life_span.unwrap();
+        let noise_field_on = config.noise_field.unwrap();
+        let noise_scale = Param::from_config(&config.noise_scale.unwrap());
+        let noise_strength = Param::from_config(&config.noise_strength.unwrap());
+        let noise_curl = Param::from_config(&config.noise_curl.unwrap());
+        let noise_time_scale = config.noise_time_scale.unwrap();
+        let noise_drift = config.noise_drift.unwrap();
+        let origin = config.origin.unwrap();
+        let flight_size = Param::from_config(&config.flight_size.unwrap());
+        let radius = Param::from_config(&config.radius.unwrap());
+        let stroke_weight = Param::from_config(&config.stroke_weight.unwrap());
+        let vortices = config.vortices.iter().flatten().map(Vortex::from_config).collect();
+        let visualize_noise_field = config.visualize_noise_field.unwrap();
         debug!("[{:?}] visualize_noise_field: {:?}", name, visualize_noise_field);
 
         let noise_field = if noise_field_on {
-            Some(nannou::noise::Perlin::new().set_seed(seed))
+            Some(NoiseField::from_config(config.noise.as_ref().unwrap(), seed))
         } else {
             None
         };
@@ -143,24 +282,41 @@ impl Emitter {
         debug!("[{:?}] mover: {:?}\ncolor_picker: {:?}", name, mover, color_picker);
         Emitter {
             name,
-            particles: Vec::new(),
+            attractors,
+            particles: ParticlePool::new(),
             mover,
             noise_field,
             noise_scale,
             noise_strength,
+            noise_curl,
+            noise_time_scale,
+            noise_drift,
+            t: 0.0,
+            vortices,
             origin,
             position: origin,
             radius,
             stroke_weight,
             randomize_position,
             randomize_velocity,
+            rng,
+            schedule,
+            seed,
             flight_size,
+            forces,
+            forces_config,
             initial_velocity,
             life_span,
+            boundary,
             bounds,
             paused: false,
             color_picker,
+            opacity: 1.0,
+            fade_step: 1.0,
+            visible: true,
             visualize_noise_field,
+            births: 0,
+            deaths: 0,
         }
     }
 
@@ -170,13 +326,13 @@ impl Emitter {
             let w = self.bounds.right - self.bounds.left;
             let h = self.bounds.top - self.bounds.bottom;
             pos = pt2(
-                ((random_f32() * 2. - 1.) * w / 2.).floor(),
-                ((random_f32() * 2. - 1.) * h / 2.).floor(),
+                ((self.rng.next_f32() * 2. - 1.) * w / 2.).floor(),
+                ((self.rng.next_f32() * 2. - 1.) * h / 2.).floor(),
             );
         }
         let vel = if self.randomize_velocity {
             // todo: make different types of randomizers
-            vec2(random_f32() * 2.0 - 1.0, random_f32() * 2.0 - 1.0)
+            vec2(self.rng.next_f32() * 2.0 - 1.0, self.rng.next_f32() * 2.0 - 1.0)
         } else {
             self.initial_velocity
         };
@@ -187,8 +343,8 @@ impl Emitter {
             pos,
             vel,
             color,
-            self.radius,
-            self.stroke_weight,
+            self.radius.value() as f32,
+            self.stroke_weight.value() as f32,
             self.life_span,
         );
         // // Apply a one-time 'gravitational' force
@@ -196,82 +352,178 @@ impl Emitter {
         particle
     }
 
+    /// Releases a flight of `flight_size` particles now.
     pub fn emit(&mut self) {
+        if self.can_emit() {
+            self.emit_particles(self.flight_size());
+        }
+    }
+
+    /// Releases whatever the emission schedule calls for over the `dt`
+    /// seconds starting at `time`.
+    pub fn emit_scheduled(&mut self, time: f64, dt: f64) {
+        if self.can_emit() {
+            let count = self
+                .schedule
+                .count(time, dt, self.flight_size(), self.particles.len());
+            self.emit_particles(count);
+        }
+    }
+
+    fn can_emit(&self) -> bool {
         if self.paused {
             trace!("[{:?}] Emitter is paused", self.name);
-            return;
+            return false;
         }
-        for _ in 0..self.flight_size {
+        self.visible
+    }
+
+    fn emit_particles(&mut self, count: usize) {
+        for _ in 0..count {
             let p = self.initializer(self.bounds);
             self.particles.push(p);
         }
+        self.births += count;
+    }
+
+    fn flight_size(&self) -> usize {
+        self.flight_size.value().max(0.0).round() as usize
     }
 
     pub fn apply_force(&mut self, force: Vec2) {
-        for p in self.particles.iter_mut() {
-            p.apply_force(force);
-        }
+        self.particles.apply_force(force);
     }
 
     pub fn toggle_pause(&mut self) {
         self.paused = !self.paused;
     }
 
-    pub fn update(&mut self, _t: f32) {
+    /// Fades the emitter in or out over `steps` steps of `dt`. A hidden emitter
+    /// stops emitting and its remaining particles fade out with it.
+    pub fn fade_to(&mut self, visible: bool, steps: u64) {
+        self.visible = visible;
+        self.fade_step = 1.0 / steps.max(1) as f32;
+        if steps == 0 {
+            self.opacity = if visible { 1.0 } else { 0.0 };
+        }
+    }
+
+    /// Sets the forces on the particles: the emitter's own, with whatever they
+    /// leave out taken from `fallback`, the scene's or the top-level forces.
+    pub fn set_forces(&mut self, fallback: &ForcesConfig) {
+        let config = self.forces_config.clone().unwrap_or_default().or(fallback);
+        // The noise field uses the seed itself.
+        self.forces = Forces::from_config(&config, self.seed.wrapping_add(1));
+    }
+
+    /// Whether the attractor called `name` acts on this emitter's particles.
+    pub fn is_attracted_by(&self, name: &str) -> bool {
+        match self.attractors {
+            Some(ref names) => names.iter().any(|n| n == name),
+            None => true,
+        }
+    }
+
+    /// Advances the emitter to sketch time `_t`, moving its particles on by
+    /// `ticks` (60ths of a second) under the noise field, `attractors` and the
+    /// emitter's forces; `frame` counts steps since the run started.
+    pub fn update(
+        &mut self,
+        _t: f32,
+        frame: u64,
+        ticks: f32,
+        integrator: Integrator,
+        attractors: &[Attractor],
+    ) {
+        for param in [
+            &mut self.flight_size,
+            &mut self.noise_scale,
+            &mut self.noise_strength,
+            &mut self.noise_curl,
+            &mut self.radius,
+            &mut self.stroke_weight,
+        ] {
+            param.update(_t, frame);
+        }
+        self.schedule.update(_t, frame);
+        self.births = 0;
+        self.t = _t;
+        self.opacity = if self.visible {
+            (self.opacity + self.fade_step).min(1.0)
+        } else {
+            (self.opacity - self.fade_step).max(0.0)
+        };
+
         // Move the emitter
         match self.mover {
-            Some(ref m) => {
+            Some(ref mut m) => {
+                m.update(_t, frame);
                 self.position = m.get_postion(_t);
                 trace!("[{:?}] position: {:?}", self.name, self.position)
             }
             _ => {}
         }
 
-        for i in (0..self.particles.len()).rev() {
-            match &self.noise_field {
-                Some(noise) => {
-                    let angle = TAU
-                        * noise.get([
-                            self.particles[i].position.x as f64 * self.noise_scale,
-                            self.particles[i].position.y as f64 * self.noise_scale,
-                            // _t as f64 * self.noise_scale,
-                            0.0 as f64,
-                        ]) as f32;
-                    let dir = vec2(angle.cos(), angle.sin());
-                    trace!("[{:?}] angle:{:?}, dir:{:?}", self.name, angle, dir);
-                    self.particles[i].update(Some(dir * self.noise_strength));
-                }
-                None => {
-                    self.particles[i].update(None);
-                }
-            }
+        let pulls: Vec<Pull> = attractors
+            .iter()
+            .filter(|a| self.is_attracted_by(a.name()))
+            .map(Attractor::pull)
+            .collect();
+        let forces = &self.forces;
+        let environment = |position: Vec2, velocity: Vec2| {
+            pulls
+                .iter()
+                .fold(vec2(0.0, 0.0), |sum, pull| sum + pull.acceleration(position))
+                + forces.acceleration(position, velocity, _t)
+        };
 
-            if self.particles[i].is_dead() {
-                self.particles.remove(i);
-            }
-        }
+        // The pool is taken out while it moves so the flow can borrow the rest
+        // of the emitter.
+        let mut particles = std::mem::take(&mut self.particles);
+        let flow = self.flow();
+        let field = |position: Vec2, velocity: Vec2| {
+            flow.acceleration(position) + environment(position, velocity)
+        };
+        self.deaths = particles.update(ticks, integrator, field);
+        self.deaths += self.boundary.apply(&mut particles);
+        self.particles = particles;
     }
 
     pub fn display(&self, draw: &Draw) {
-        for p in self.particles.iter() {
-            p.display(draw);
+        if self.opacity > 0.0 {
+            self.particles.display_faded(draw, self.opacity);
         }
-        if self.visualize_noise_field {
+        if self.visualize_noise_field && self.opacity > 0.0 {
             self.draw_flow_field(draw);
         }
     }
 
+    /// The flow field as of the last update, with the noise scrolled and
+    /// evolved to its time. Particles and the drawn field both go through
+    /// this, so the two stay in step.
+    fn flow(&self) -> Flow<'_> {
+        Flow {
+            noise: self.noise_field.as_ref(),
+            scale: self.noise_scale.value(),
+            ticks: self.t as f64 * SECONDS_PER_T * TICKS_PER_SECOND,
+            drift: self.noise_drift,
+            time_scale: self.noise_time_scale as f64,
+            strength: self.noise_strength.value() as f32,
+            curl: self.noise_curl.value() as f32,
+            vortices: &self.vortices,
+        }
+    }
+
+    /// Arrows along the flow field's direction, however strong it is.
     pub fn draw_flow_field(&self, draw: &Draw) {
+        if self.noise_field.is_none() && self.vortices.is_empty() {
+            return;
+        }
+        let flow = self.flow();
         let step: f32 = 10.0;
         for x in (self.bounds.left as i32..self.bounds.right as i32).step_by(step as usize) {
             for y in (self.bounds.bottom as i32..self.bounds.top as i32).step_by(step as usize) {
-                let angle = TAU
-                    * self.noise_field.as_ref().unwrap().get([
-                        x as f64 * self.noise_scale,
-                        y as f64 * self.noise_scale,
-                        0.0 as f64,
-                    ]) as f32;
-                let dir = vec2(angle.cos(), angle.sin());
+                let dir = flow.acceleration(pt2(x as f32, y as f32)).normalize_or_zero();
                 draw.arrow()
                     .start(pt2(x as f32, y as f32))
                     .end(pt2(x as f32 + dir.x * 10.0, y as f32 + dir.y * 10.0))
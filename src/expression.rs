@@ -0,0 +1,404 @@
+use super::config::Animated;
+
+use log::*;
+use std::fmt;
+
+/// An arithmetic expression in `t` (sketch time) and `step` (steps of `dt` since
+/// the run, or sweep variant, started; also accepted as `frame`, though it
+/// doesn't count rendered frames), e.g. `0.5 + 0.2*sin(t*3)`. Supports
+/// `+ - * / % ^`, parentheses, `pi`, `tau` and the functions in `Function`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    T,
+    Step,
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Abs,
+    Sqrt,
+    Exp,
+    Ln,
+    Floor,
+    Ceil,
+    Fract,
+    Min,
+    Max,
+    Clamp,
+}
+
+const FUNCTIONS: &[(&str, Function)] = &[
+    ("sin", Function::Sin),
+    ("cos", Function::Cos),
+    ("tan", Function::Tan),
+    ("abs", Function::Abs),
+    ("sqrt", Function::Sqrt),
+    ("exp", Function::Exp),
+    ("ln", Function::Ln),
+    ("floor", Function::Floor),
+    ("ceil", Function::Ceil),
+    ("fract", Function::Fract),
+    ("min", Function::Min),
+    ("max", Function::Max),
+    ("clamp", Function::Clamp),
+];
+
+impl Function {
+    fn arity(self) -> usize {
+        match self {
+            Function::Min | Function::Max => 2,
+            Function::Clamp => 3,
+            _ => 1,
+        }
+    }
+
+    fn apply(self, args: &[f64]) -> f64 {
+        match self {
+            Function::Sin => args[0].sin(),
+            Function::Cos => args[0].cos(),
+            Function::Tan => args[0].tan(),
+            Function::Abs => args[0].abs(),
+            Function::Sqrt => args[0].sqrt(),
+            Function::Exp => args[0].exp(),
+            Function::Ln => args[0].ln(),
+            Function::Floor => args[0].floor(),
+            Function::Ceil => args[0].ceil(),
+            Function::Fract => args[0].fract(),
+            Function::Min => args[0].min(args[1]),
+            Function::Max => args[0].max(args[1]),
+            Function::Clamp => args[0].max(args[1]).min(args[2]),
+        }
+    }
+}
+
+impl Expr {
+    pub fn eval(&self, t: f64, step: f64) -> f64 {
+        match self {
+            Expr::Number(n) => *n,
+            Expr::T => t,
+            Expr::Step => step,
+            Expr::Neg(e) => -e.eval(t, step),
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(t, step), b.eval(t, step));
+                match op {
+                    Op::Add => a + b,
+                    Op::Sub => a - b,
+                    Op::Mul => a * b,
+                    Op::Div => a / b,
+                    Op::Rem => a % b,
+                    Op::Pow => a.powf(b),
+                }
+            }
+            Expr::Call(function, args) => {
+                let args: Vec<f64> = args.iter().map(|a| a.eval(t, step)).collect();
+                function.apply(&args)
+            }
+        }
+    }
+}
+
+/// Why an expression failed to parse, with the 1-based column it failed at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub column: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.column)
+    }
+}
+
+pub fn parse(text: &str) -> Result<Expr, ParseError> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        pos: 0,
+    };
+    let expr = parser.sum()?;
+    parser.skip_whitespace();
+    match parser.peek() {
+        None => Ok(expr),
+        Some(c) => Err(parser.error(format!("unexpected {:?}", c))),
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Consumes `c` if it is the next non-blank character.
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        ParseError {
+            message,
+            column: self.pos + 1,
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.product()?;
+        loop {
+            let op = if self.eat('+') {
+                Op::Add
+            } else if self.eat('-') {
+                Op::Sub
+            } else {
+                return Ok(expr);
+            };
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.product()?));
+        }
+    }
+
+    fn product(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.unary()?;
+        loop {
+            let op = if self.eat('*') {
+                Op::Mul
+            } else if self.eat('/') {
+                Op::Div
+            } else if self.eat('%') {
+                Op::Rem
+            } else {
+                return Ok(expr);
+            };
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.eat('-') {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        let base = self.atom()?;
+        if self.eat('^') {
+            // Right associative, and binds tighter than a leading minus.
+            return Ok(Expr::Binary(
+                Op::Pow,
+                Box::new(base),
+                Box::new(self.unary()?),
+            ));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Expr, ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let expr = self.sum()?;
+                if !self.eat(')') {
+                    return Err(self.error("expected ')'".to_string()));
+                }
+                Ok(expr)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(),
+            Some(c) if c.is_alphabetic() || c == '_' => self.name(),
+            Some(c) => Err(self.error(format!("unexpected {:?}", c))),
+            None => Err(self.error("unexpected end of expression".to_string())),
+        }
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(&f) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn number(&mut self) -> Result<Expr, ParseError> {
+        let start = self.pos;
+        let mut text = self.take_while(|c| c.is_ascii_digit() || c == '.');
+        // Exponent, as in `1e-3`.
+        if matches!(self.peek(), Some('e') | Some('E')) {
+            text.push('e');
+            self.pos += 1;
+            if let Some(sign) = self.peek().filter(|c| *c == '-' || *c == '+') {
+                text.push(sign);
+                self.pos += 1;
+            }
+            text.push_str(&self.take_while(|c| c.is_ascii_digit()));
+        }
+        text.parse().map(Expr::Number).map_err(|_| ParseError {
+            message: format!("invalid number {:?}", text),
+            column: start + 1,
+        })
+    }
+
+    fn name(&mut self) -> Result<Expr, ParseError> {
+        let start = self.pos;
+        let name = self.take_while(|c| c.is_alphanumeric() || c == '_');
+        match name.as_str() {
+            "t" => return Ok(Expr::T),
+            "step" | "frame" => return Ok(Expr::Step),
+            "pi" => return Ok(Expr::Number(std::f64::consts::PI)),
+            "tau" => return Ok(Expr::Number(std::f64::consts::TAU)),
+            _ => {}
+        }
+        let function = match FUNCTIONS.iter().find(|(n, _)| *n == name) {
+            Some((_, function)) => *function,
+            None => {
+                return Err(ParseError {
+                    message: format!("unknown name {:?}", name),
+                    column: start + 1,
+                })
+            }
+        };
+        if !self.eat('(') {
+            return Err(self.error(format!("expected '(' after {}", name)));
+        }
+        let mut args = Vec::new();
+        if !self.eat(')') {
+            loop {
+                args.push(self.sum()?);
+                if self.eat(')') {
+                    break;
+                }
+                if !self.eat(',') {
+                    return Err(self.error("expected ',' or ')'".to_string()));
+                }
+            }
+        }
+        if args.len() != function.arity() {
+            return Err(ParseError {
+                message: format!("{} takes {} argument(s)", name, function.arity()),
+                column: start + 1,
+            });
+        }
+        Ok(Expr::Call(function, args))
+    }
+}
+
+/// A numeric setting that is either fixed or re-evaluated from an expression
+/// every step.
+#[derive(Debug, Clone)]
+pub struct Param {
+    value: f64,
+    expr: Option<Expr>,
+}
+
+impl Param {
+    pub fn constant(value: f64) -> Self {
+        Param { value, expr: None }
+    }
+
+    /// Compiles a config value. `validation` reports bad expressions when the
+    /// config is read, so one that still fails here is logged and reads as 0.
+    pub fn from_config(value: &Animated) -> Self {
+        match value {
+            Animated::Constant(value) => Param::constant(*value),
+            Animated::Expression(text) => match parse(text) {
+                Ok(expr) => Param {
+                    value: expr.eval(0.0, 0.0),
+                    expr: Some(expr),
+                },
+                Err(e) => {
+                    error!("Invalid expression {:?}: {}", text, e);
+                    Param::constant(0.0)
+                }
+            },
+        }
+    }
+
+    pub fn update(&mut self, t: f32, step: u64) {
+        if let Some(ref expr) = self.expr {
+            self.value = expr.eval(t as f64, step as f64);
+        }
+    }
+
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(text: &str, t: f64) -> f64 {
+        parse(text).unwrap().eval(t, 10.0)
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(eval("1 + 2 * 3", 0.0), 7.0);
+        assert_eq!(eval("(1 + 2) * 3", 0.0), 9.0);
+        assert_eq!(eval("-2^2", 0.0), -4.0);
+        assert_eq!(eval("2^3^2", 0.0), 512.0);
+        assert_eq!(eval("10 - 4 - 3", 0.0), 3.0);
+        assert_eq!(eval("1e-3 * step", 0.0), 0.01);
+        assert_eq!(eval("1e-3 * frame", 0.0), 0.01);
+    }
+
+    #[test]
+    fn test_variables_and_functions() {
+        assert_eq!(eval("0.5 + 0.2*sin(t*3)", 0.0), 0.5);
+        assert!((eval("0.5 + 0.2*sin(t*3)", pi_over(6.0)) - 0.7).abs() < 1e-12);
+        assert_eq!(eval("clamp(t, 0, 1) + max(step, 2)", 3.0), 11.0);
+    }
+
+    fn pi_over(n: f64) -> f64 {
+        std::f64::consts::PI / n
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |text: &str| parse(text).unwrap_err().to_string();
+        assert_eq!(error("1 +"), "unexpected end of expression at column 4");
+        assert_eq!(error("sin(t"), "expected ',' or ')' at column 6");
+        assert_eq!(error("2 * x"), "unknown name \"x\" at column 5");
+        assert_eq!(error("max(t)"), "max takes 2 argument(s) at column 1");
+        assert_eq!(error("1 2"), "unexpected '2' at column 3");
+    }
+
+    #[test]
+    fn test_param() {
+        let mut param = Param::from_config(&Animated::Expression("step / 2".to_string()));
+        assert_eq!(param.value(), 0.0);
+        param.update(0.0, 8);
+        assert_eq!(param.value(), 4.0);
+        let mut param = Param::from_config(&Animated::Constant(3.0));
+        param.update(1.0, 8);
+        assert_eq!(param.value(), 3.0);
+    }
+}
@@ -0,0 +1,363 @@
+use super::attractor::{MIN_DISTANCE, REFERENCE_DISTANCE};
+use super::config::{NoiseConfig, VortexConfig};
+
+use nannou::noise::{
+    Billow, Fbm, MultiFractal, NoiseFn, OpenSimplex, Perlin, RidgedMulti, Seedable, SuperSimplex,
+    Turbulence, Value, Worley,
+};
+use nannou::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Noise functions a flow field can be built on.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseAlgorithm {
+    #[default]
+    Perlin,
+    OpenSimplex,
+    SuperSimplex,
+    Value,
+    /// Cellular noise: flat cells with sharp edges between them.
+    Worley,
+    /// Fractal Brownian motion: octaves of Perlin noise, each finer and fainter.
+    Fbm,
+    /// Like `fbm` with each octave folded, giving puffy, cloud-like shapes.
+    Billow,
+    /// Like `fbm` with each octave folded the other way, giving sharp ridges.
+    RidgedMulti,
+}
+
+/// Step, in noise units, of the differences `NoiseField::curl` takes.
+const CURL_STEP: f64 = 1e-4;
+
+type Source = dyn NoiseFn<[f64; 3]> + Send + Sync;
+
+/// A noise function over the plane and time, built from a `NoiseConfig`. Both
+/// moving particles and drawing the flow field go through one of these, so the
+/// arrows always show what the particles feel.
+pub struct NoiseField {
+    algorithm: NoiseAlgorithm,
+    frequency: f64,
+    source: Box<Source>,
+}
+
+impl NoiseField {
+    /// `config` must have been through `with_defaults`.
+    pub fn from_config(config: &NoiseConfig, seed: u32) -> Self {
+        let algorithm = config.algorithm.unwrap();
+        let octaves = config.octaves.unwrap();
+        let lacunarity = config.lacunarity.unwrap() as f64;
+        let persistence = config.persistence.unwrap() as f64;
+        let warp = config.warp.unwrap() as f64;
+        let warp_frequency = config.warp_frequency.unwrap() as f64;
+        let warped = |f| warped(f, seed, warp, warp_frequency);
+        let source = match algorithm {
+            NoiseAlgorithm::Perlin => warped(Box::new(Perlin::new().set_seed(seed))),
+            NoiseAlgorithm::OpenSimplex => warped(Box::new(OpenSimplex::new().set_seed(seed))),
+            NoiseAlgorithm::SuperSimplex => warped(Box::new(SuperSimplex::new().set_seed(seed))),
+            NoiseAlgorithm::Value => warped(Box::new(Value::new().set_seed(seed))),
+            NoiseAlgorithm::Worley => warped(Box::new(Worley::new().set_seed(seed))),
+            NoiseAlgorithm::Fbm => warped(Box::new(fractal(
+                Fbm::new().set_seed(seed),
+                octaves,
+                lacunarity,
+                persistence,
+            ))),
+            NoiseAlgorithm::Billow => warped(Box::new(fractal(
+                Billow::new().set_seed(seed),
+                octaves,
+                lacunarity,
+                persistence,
+            ))),
+            NoiseAlgorithm::RidgedMulti => warped(Box::new(fractal(
+                RidgedMulti::new().set_seed(seed),
+                octaves,
+                lacunarity,
+                persistence,
+            ))),
+        };
+        NoiseField {
+            algorithm,
+            frequency: config.frequency.unwrap() as f64,
+            source,
+        }
+    }
+
+    pub fn algorithm(&self) -> NoiseAlgorithm {
+        self.algorithm
+    }
+
+    /// The noise, roughly in `[-1, 1]`, at `position` scaled by `scale` and at
+    /// depth `z`.
+    pub fn get(&self, position: Vec2, scale: f64, z: f64) -> f64 {
+        self.source.get(self.point(position, scale, z))
+    }
+
+    /// The unit vector the noise at `position` points along: a full turn over
+    /// the noise's range.
+    pub fn direction(&self, position: Vec2, scale: f64, z: f64) -> Vec2 {
+        let angle = TAU * self.get(position, scale, z) as f32;
+        vec2(angle.cos(), angle.sin())
+    }
+
+    /// The curl of the noise at `position`: its gradient turned a quarter turn
+    /// clockwise, so it runs along the noise's contours and never converges.
+    /// Taken per noise unit, so its size doesn't depend on `scale`.
+    pub fn curl(&self, position: Vec2, scale: f64, z: f64) -> Vec2 {
+        let [x, y, z] = self.point(position, scale, z);
+        let dx = self.source.get([x + CURL_STEP, y, z]) - self.source.get([x - CURL_STEP, y, z]);
+        let dy = self.source.get([x, y + CURL_STEP, z]) - self.source.get([x, y - CURL_STEP, z]);
+        vec2(dy as f32, -dx as f32) / (2.0 * CURL_STEP) as f32
+    }
+
+    fn point(&self, position: Vec2, scale: f64, z: f64) -> [f64; 3] {
+        let scale = scale * self.frequency;
+        [
+            position.x as f64 * scale,
+            position.y as f64 * scale,
+            z * self.frequency,
+        ]
+    }
+}
+
+impl fmt::Debug for NoiseField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NoiseField")
+            .field("algorithm", &self.algorithm)
+            .field("frequency", &self.frequency)
+            .finish()
+    }
+}
+
+/// A point particles circle around, harder the closer they are.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vortex {
+    pub center: Point2,
+    /// Signed: positive turns counter-clockwise.
+    pub strength: f32,
+    pub falloff: f32,
+    pub radius: f32,
+}
+
+impl Vortex {
+    pub fn from_config(config: &VortexConfig) -> Self {
+        let config = config.clone().with_defaults();
+        Vortex {
+            center: config.center.unwrap(),
+            strength: config.strength.unwrap(),
+            falloff: config.falloff.unwrap(),
+            radius: config.radius.unwrap_or(f32::INFINITY),
+        }
+    }
+
+    /// Acceleration, in pixels per tick per tick, of a particle at `position`:
+    /// square to the line to the center, so it swirls without pulling in.
+    pub fn acceleration(&self, position: Vec2) -> Vec2 {
+        let offset = position - self.center;
+        let distance = offset.length();
+        if distance == 0.0 || distance > self.radius {
+            return vec2(0.0, 0.0);
+        }
+        let scale = REFERENCE_DISTANCE / distance.max(MIN_DISTANCE);
+        vec2(-offset.y, offset.x) / distance * self.strength * scale.powf(self.falloff)
+    }
+}
+
+/// Everything an emitter's flow field pushes particles with at one instant:
+/// the angle and curl of its noise and its vortices.
+#[derive(Debug, Clone, Copy)]
+pub struct Flow<'a> {
+    pub noise: Option<&'a NoiseField>,
+    pub scale: f64,
+    /// Ticks since the run started, which the noise has scrolled and evolved
+    /// for.
+    pub ticks: f64,
+    /// Pixels per tick the noise scrolls by.
+    pub drift: Vec2,
+    /// Noise units per tick the noise evolves by; see `NoiseField::get`.
+    pub time_scale: f64,
+    /// Strength of the angle field.
+    pub strength: f32,
+    /// Strength of the curl field.
+    pub curl: f32,
+    pub vortices: &'a [Vortex],
+}
+
+impl Flow<'_> {
+    /// Acceleration, in pixels per tick per tick, of a particle at `position`.
+    pub fn acceleration(&self, position: Vec2) -> Vec2 {
+        let mut acceleration = vec2(0.0, 0.0);
+        if let Some(noise) = self.noise {
+            let p = position - self.drift * self.ticks as f32;
+            let z = self.ticks * self.time_scale;
+            acceleration += noise.direction(p, self.scale, z) * self.strength;
+            if self.curl != 0.0 {
+                acceleration += noise.curl(p, self.scale, z) * self.curl;
+            }
+        }
+        self.vortices
+            .iter()
+            .fold(acceleration, |sum, v| sum + v.acceleration(position))
+    }
+}
+
+/// `f` with its octaves set. Its first octave is sampled at frequency 1: the
+/// field's frequency is applied to the input instead, as for other algorithms.
+fn fractal<F: MultiFractal>(f: F, octaves: usize, lacunarity: f64, persistence: f64) -> F {
+    f.set_octaves(octaves)
+        .set_frequency(1.0)
+        .set_lacunarity(lacunarity)
+        .set_persistence(persistence)
+}
+
+/// `source`, with its input displaced by more noise when `warp` isn't zero.
+fn warped(source: Box<Source>, seed: u32, warp: f64, frequency: f64) -> Box<Source> {
+    if warp == 0.0 {
+        return source;
+    }
+    Box::new(
+        Turbulence::new(Warp(source))
+            .set_seed(seed.wrapping_add(1))
+            .set_frequency(frequency)
+            .set_power(warp),
+    )
+}
+
+/// Lets a boxed source sit inside a `Turbulence`, which the noise crate only
+/// implements for sized sources.
+struct Warp(Box<Source>);
+
+impl NoiseFn<[f64; 3]> for Warp {
+    fn get(&self, point: [f64; 3]) -> f64 {
+        self.0.get(point)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALGORITHMS: [NoiseAlgorithm; 8] = [
+        NoiseAlgorithm::Perlin,
+        NoiseAlgorithm::OpenSimplex,
+        NoiseAlgorithm::SuperSimplex,
+        NoiseAlgorithm::Value,
+        NoiseAlgorithm::Worley,
+        NoiseAlgorithm::Fbm,
+        NoiseAlgorithm::Billow,
+        NoiseAlgorithm::RidgedMulti,
+    ];
+
+    fn field(algorithm: NoiseAlgorithm, warp: f32) -> NoiseField {
+        let config = NoiseConfig {
+            algorithm: Some(algorithm),
+            warp: Some(warp),
+            ..Default::default()
+        };
+        NoiseField::from_config(&config.with_defaults(), 4)
+    }
+
+    fn samples(field: &NoiseField) -> Vec<f64> {
+        (0..50)
+            .map(|i| field.get(pt2(i as f32 * 13.7, i as f32 * -5.3), 0.01, 0.5))
+            .collect()
+    }
+
+    #[test]
+    fn test_algorithms_differ() {
+        let all: Vec<Vec<f64>> = ALGORITHMS
+            .iter()
+            .map(|a| samples(&field(*a, 0.0)))
+            .collect();
+        for (i, a) in all.iter().enumerate() {
+            assert!(
+                a.iter().all(|x| x.is_finite() && x.abs() <= 1.5),
+                "{:?}",
+                ALGORITHMS[i]
+            );
+            assert!(a.iter().any(|x| *x != a[0]), "{:?} is flat", ALGORITHMS[i]);
+            for b in all[..i].iter() {
+                assert_ne!(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_perlin_matches_plain_perlin() {
+        // Configs from before there was a choice keep their fields.
+        let perlin = Perlin::new().set_seed(4);
+        let field = field(NoiseAlgorithm::Perlin, 0.0);
+        let p = pt2(120.0, -40.0);
+        assert_eq!(
+            field.get(p, 0.01, 0.0),
+            perlin.get([120.0 * 0.01, -40.0 * 0.01, 0.0])
+        );
+        assert_eq!(field.direction(p, 0.01, 0.0).length().round(), 1.0);
+    }
+
+    #[test]
+    fn test_curl_is_divergence_free() {
+        let field = field(NoiseAlgorithm::Perlin, 0.0);
+        let divergence = |f: &dyn Fn(Vec2) -> Vec2, p: Vec2| {
+            let h = 0.5;
+            (f(p + vec2(h, 0.0)).x - f(p - vec2(h, 0.0)).x + f(p + vec2(0.0, h)).y
+                - f(p - vec2(0.0, h)).y)
+                / (2.0 * h)
+        };
+        let curl = |p| field.curl(p, 0.01, 0.3);
+        let angle = |p| field.direction(p, 0.01, 0.3);
+        let points: Vec<Vec2> = (0..20)
+            .map(|i| pt2(i as f32 * 31.0, i as f32 * -17.0))
+            .collect();
+        assert!(points.iter().all(|p| divergence(&curl, *p).abs() < 1e-3));
+        assert!(points.iter().any(|p| divergence(&angle, *p).abs() > 1e-2));
+        assert!(points.iter().any(|p| curl(*p).length() > 0.1));
+    }
+
+    #[test]
+    fn test_vortex_and_flow() {
+        let vortex = Vortex::from_config(&VortexConfig {
+            center: Some(pt2(100.0, 0.0)),
+            strength: Some(0.5),
+            radius: Some(300.0),
+            ..Default::default()
+        });
+        // Counter-clockwise, half as strong at twice the distance.
+        assert_eq!(vortex.acceleration(pt2(200.0, 0.0)), vec2(0.0, 0.5));
+        assert_eq!(vortex.acceleration(pt2(100.0, 200.0)), vec2(-0.25, 0.0));
+        assert_eq!(vortex.acceleration(pt2(100.0, 0.0)), vec2(0.0, 0.0));
+        assert_eq!(vortex.acceleration(pt2(100.0, 301.0)), vec2(0.0, 0.0));
+
+        let noise = field(NoiseAlgorithm::Perlin, 0.0);
+        let flow = Flow {
+            noise: Some(&noise),
+            scale: 0.01,
+            ticks: 10.0,
+            drift: vec2(0.5, 0.0),
+            time_scale: 0.1,
+            strength: 0.5,
+            curl: 0.25,
+            vortices: &[vortex],
+        };
+        let p = pt2(200.0, 0.0);
+        // Scrolled 5 pixels and evolved 1 noise unit over 10 ticks.
+        let expected = noise.direction(pt2(195.0, 0.0), 0.01, 1.0) * 0.5
+            + noise.curl(pt2(195.0, 0.0), 0.01, 1.0) * 0.25
+            + vec2(0.0, 0.5);
+        assert_eq!(flow.acceleration(p), expected);
+        let still = Flow {
+            noise: None,
+            ..flow
+        };
+        assert_eq!(still.acceleration(p), vec2(0.0, 0.5));
+    }
+
+    #[test]
+    fn test_warp() {
+        let plain = samples(&field(NoiseAlgorithm::Fbm, 0.0));
+        let warped = samples(&field(NoiseAlgorithm::Fbm, 0.5));
+        assert_ne!(plain, warped);
+        assert_eq!(warped, samples(&field(NoiseAlgorithm::Fbm, 0.5)));
+    }
+}
@@ -0,0 +1,105 @@
+use super::config::ForcesConfig;
+
+use nannou::noise::{NoiseFn, Perlin, Seedable};
+use nannou::prelude::*;
+
+/// Where the second turbulence component samples the noise, far enough from
+/// the first that the two are unrelated.
+const TURBULENCE_OFFSET: f64 = 71.3;
+
+/// Forces from the environment rather than from other particles: gravity, wind
+/// and drag. Accelerations are in pixels per tick per tick.
+#[derive(Debug, Clone)]
+pub struct Forces {
+    gravity: Vec2,
+    wind: Vec2,
+    turbulence: f32,
+    turbulence_scale: f64,
+    linear_drag: f32,
+    quadratic_drag: f32,
+    noise: Perlin,
+}
+
+impl Forces {
+    /// Missing fields of `config` are taken as no force. `seed` picks the
+    /// turbulence.
+    pub fn from_config(config: &ForcesConfig, seed: u32) -> Self {
+        let config = config.clone().with_defaults();
+        Forces {
+            gravity: config.gravity.unwrap(),
+            wind: config.wind.unwrap(),
+            turbulence: config.turbulence.unwrap(),
+            turbulence_scale: config.turbulence_scale.unwrap() as f64,
+            linear_drag: config.linear_drag.unwrap(),
+            quadratic_drag: config.quadratic_drag.unwrap(),
+            noise: Perlin::new().set_seed(seed),
+        }
+    }
+
+    /// Acceleration of a particle at `position` moving at `velocity`, at
+    /// sketch time `t`. Turbulence drifts with `t`, so gusts come and go.
+    pub fn acceleration(&self, position: Vec2, velocity: Vec2, t: f32) -> Vec2 {
+        let mut acceleration = self.gravity + self.wind;
+        if self.turbulence != 0.0 {
+            let x = position.x as f64 * self.turbulence_scale;
+            let y = position.y as f64 * self.turbulence_scale;
+            let gust = vec2(
+                self.noise.get([x, y, t as f64]) as f32,
+                self.noise.get([x + TURBULENCE_OFFSET, y, t as f64]) as f32,
+            );
+            acceleration += gust * self.turbulence;
+        }
+        let drag = self.linear_drag + self.quadratic_drag * velocity.length();
+        acceleration - velocity * drag
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gravity_and_drag() {
+        let config = ForcesConfig {
+            gravity: Some(vec2(0.0, -0.1)),
+            linear_drag: Some(0.25),
+            quadratic_drag: Some(0.0625),
+            ..Default::default()
+        };
+        let forces = Forces::from_config(&config, 0);
+        assert_eq!(
+            forces.acceleration(pt2(5.0, 5.0), vec2(0.0, 0.0), 0.0),
+            vec2(0.0, -0.1)
+        );
+        // Linear drag 0.25 and quadratic drag 0.0625 * 8 both oppose the motion.
+        assert_eq!(
+            forces.acceleration(pt2(5.0, 5.0), vec2(8.0, 0.0), 0.0),
+            vec2(-6.0, -0.1)
+        );
+        let still = Forces::from_config(&ForcesConfig::default(), 0);
+        assert_eq!(
+            still.acceleration(pt2(5.0, 5.0), vec2(3.0, 4.0), 1.0),
+            vec2(0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn test_turbulence() {
+        let config = ForcesConfig {
+            wind: Some(vec2(0.5, 0.0)),
+            turbulence: Some(0.2),
+            turbulence_scale: Some(0.01),
+            ..Default::default()
+        };
+        let forces = Forces::from_config(&config, 3);
+        let gusts: Vec<Vec2> = (0..20)
+            .map(|i| forces.acceleration(pt2(i as f32 * 37.0, 11.0), vec2(0.0, 0.0), 0.3))
+            .collect();
+        assert!(gusts
+            .iter()
+            .all(|g| (g.x - 0.5).abs() <= 0.2 && g.y.abs() <= 0.2));
+        assert!(gusts.iter().any(|g| *g != gusts[0]));
+        let later = forces.acceleration(pt2(37.0, 11.0), vec2(0.0, 0.0), 0.8);
+        assert_ne!(later, gusts[1]);
+    }
+}
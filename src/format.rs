@@ -0,0 +1,182 @@
+use super::validation::{Diagnostic, Location, Problem, Report, Severity};
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use toml_edit::{ImDocument, Table};
+
+/// File formats a config can be written in. Every format is read into the same
+/// TOML table, so migrations, inheritance, defaults and validation apply alike.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Toml,
+    Json,
+    Yaml,
+    Ron,
+}
+
+impl Format {
+    /// The format implied by a file's extension, e.g. `config.yaml`.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Format> {
+        path.as_ref()
+            .extension()
+            .and_then(|e| e.to_str())
+            .and_then(|e| e.parse().ok())
+    }
+
+    /// Parses `contents` into a TOML table. Only TOML tables carry the spans
+    /// later diagnostics use for line numbers; other formats report their own
+    /// syntax errors with a location and everything after that by path.
+    pub fn parse(self, contents: &str) -> Result<Table, Report> {
+        let value: toml::Value = match self {
+            Format::Toml => {
+                return ImDocument::parse(contents)
+                    .map(ImDocument::into_table)
+                    .map_err(|e| Report::parse_error(e.message(), e.span(), contents));
+            }
+            Format::Json => serde_json::from_str(contents)
+                .map_err(|e| syntax_error(e.to_string(), e.line(), e.column()))?,
+            Format::Yaml => serde_yaml::from_str(contents).map_err(|e| match e.location() {
+                Some(l) => syntax_error(e.to_string(), l.line(), l.column()),
+                None => syntax_error(e.to_string(), 0, 0),
+            })?,
+            Format::Ron => {
+                // `toml::Value` can't deserialize RON structs with more than one
+                // field directly, so go through RON's own value type.
+                let value: ron::Value = ron::from_str(contents).map_err(|e| {
+                    syntax_error(e.code.to_string(), e.position.line, e.position.col)
+                })?;
+                toml::Value::try_from(value).map_err(|e| syntax_error(e.to_string(), 0, 0))?
+            }
+        };
+        if !value.is_table() {
+            return Err(syntax_error(
+                format!("a {} config must be a map at the top level", self),
+                0,
+                0,
+            ));
+        }
+        toml_edit::ser::to_document(&value)
+            .map(|document| document.as_table().clone())
+            .map_err(|e| syntax_error(e.to_string(), 0, 0))
+    }
+}
+
+/// A report for a syntax error at a 1-based `line` and `column`, or 0 if unknown.
+fn syntax_error(message: String, line: usize, column: usize) -> Report {
+    let suffix = format!(" at line {} column {}", line, column);
+    let message = message.strip_suffix(&suffix).unwrap_or(&message);
+    Report {
+        diagnostics: vec![Diagnostic {
+            severity: Severity::Error,
+            problem: Problem::Parse(message.to_string()),
+            path: String::new(),
+            location: if line > 0 {
+                Some(Location { line, column })
+            } else {
+                None
+            },
+        }],
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "toml" => Ok(Format::Toml),
+            "json" => Ok(Format::Json),
+            "yaml" | "yml" => Ok(Format::Yaml),
+            "ron" => Ok(Format::Ron),
+            _ => Err(format!(
+                "unknown config format {:?} (expected toml, json, yaml or ron)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Format::Toml => write!(f, "TOML"),
+            Format::Json => write!(f, "JSON"),
+            Format::Yaml => write!(f, "YAML"),
+            Format::Ron => write!(f, "RON"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{parse_config_as, Config};
+
+    const TOML: &str = r#"
+selected_emitters = ["e"]
+[emitters.e]
+color_picker = "green"
+radius = 4
+[color_pickers.green]
+hue = 120
+range_lightness = [0.3, 0.7]
+"#;
+
+    #[test]
+    fn test_formats_read_the_same_config() {
+        let json = r#"{
+            "selected_emitters": ["e"],
+            "emitters": { "e": { "color_picker": "green", "radius": 4 } },
+            "color_pickers": { "green": { "hue": 120, "range_lightness": [0.3, 0.7] } }
+        }"#;
+        let yaml = "
+selected_emitters: [e]
+emitters:
+  e: { color_picker: green, radius: 4 }
+color_pickers:
+  green:
+    hue: 120
+    range_lightness: [0.3, 0.7]
+";
+        let ron = r#"(
+            selected_emitters: ["e"],
+            emitters: { "e": (color_picker: "green", radius: 4) },
+            color_pickers: { "green": (hue: 120, range_lightness: (0.3, 0.7)) },
+        )"#;
+        let expected: Config = parse_config_as(TOML, Format::Toml, &[]).unwrap();
+        for (contents, format) in [
+            (json, Format::Json),
+            (yaml, Format::Yaml),
+            (ron, Format::Ron),
+        ] {
+            assert_eq!(
+                parse_config_as(contents, format, &[]).unwrap(),
+                expected,
+                "{}",
+                format
+            );
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let report = parse_config_as("{\n  \"seed\": 1,\n}", Format::Json, &[]).unwrap_err();
+        assert_eq!(
+            report.diagnostics[0].location,
+            Some(Location { line: 3, column: 1 })
+        );
+
+        // Validation applies as it does to TOML, by path rather than line.
+        let report = parse_config_as("selected_emitters: [nope]", Format::Yaml, &[]).unwrap_err();
+        assert_eq!(report.diagnostics[0].path, "selected_emitters.0");
+        assert_eq!(report.diagnostics[0].location, None);
+    }
+
+    #[test]
+    fn test_from_path() {
+        assert_eq!(Format::from_path("a/config.YML"), Some(Format::Yaml));
+        assert_eq!(Format::from_path("config.ron"), Some(Format::Ron));
+        assert_eq!(Format::from_path("config"), None);
+    }
+}
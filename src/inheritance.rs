@@ -0,0 +1,298 @@
+use super::validation::{location, Diagnostic, Problem, Report, Severity};
+
+use std::collections::HashMap;
+use toml_edit::{Item, Key, Table};
+
+/// Sections whose entries may `extends` a sibling, paired with the name of
+/// their shared table under `[defaults]`.
+const SECTIONS: &[(&str, &str)] = &[
+    ("emitters", "emitter"),
+    ("color_pickers", "color_picker"),
+    ("movers", "mover"),
+    ("attractors", "attractor"),
+];
+
+/// The section whose entries inherit `[defaults.<kind>]`, if `kind` is one.
+pub fn section(kind: &str) -> Option<&'static str> {
+    SECTIONS
+        .iter()
+        .find(|(_, k)| *k == kind)
+        .map(|(section, _)| *section)
+}
+
+/// Flattens `extends = "other"` chains and `[defaults.<kind>]` tables into each
+/// entry so the rest of the pipeline only sees self-contained sections. Nested
+/// tables such as `emission` are merged key by key. Values keep the span of the place they were written, so later diagnostics still
+/// point at the right line even when a value was inherited.
+pub fn resolve(root: &mut Table, source: &str, report: &mut Report) {
+    let defaults = match root.remove("defaults") {
+        Some(Item::Table(defaults)) => defaults,
+        Some(item) => {
+            push(
+                report,
+                source,
+                Problem::Parse("`defaults` must be a table".to_string()),
+                "defaults",
+                &item,
+            );
+            Table::new()
+        }
+        None => Table::new(),
+    };
+    for (key, item) in defaults.iter() {
+        if !SECTIONS.iter().any(|(_, kind)| *kind == key) {
+            let key_span = defaults.key(key).and_then(Key::span);
+            report.push(Diagnostic {
+                severity: Severity::Warning,
+                problem: Problem::UnknownKey(key.to_string()),
+                path: format!("defaults.{}", key),
+                location: key_span
+                    .or_else(|| item.span())
+                    .map(|s| location(source, s.start)),
+            });
+        }
+    }
+
+    for (section, kind) in SECTIONS {
+        let defaults = fields(defaults.get(kind));
+        let entries = match root.get_mut(section).and_then(Item::as_table_like_mut) {
+            Some(entries) => entries,
+            None => continue,
+        };
+        let own: HashMap<String, Vec<(Key, Item)>> = entries
+            .iter()
+            .map(|(name, item)| (name.to_string(), fields(Some(item))))
+            .collect();
+        let mut resolver = Resolver {
+            section,
+            source,
+            own: &own,
+            defaults: &defaults,
+            resolved: HashMap::new(),
+            report,
+        };
+        let mut names: Vec<&String> = own.keys().collect();
+        names.sort();
+        for name in names {
+            let merged = resolver.resolve(name, &mut Vec::new());
+            let mut table = Table::new();
+            for (key, item) in merged {
+                table.insert_formatted(&key, item);
+            }
+            entries.insert(name, Item::Table(table));
+        }
+    }
+}
+
+struct Resolver<'a> {
+    section: &'a str,
+    source: &'a str,
+    own: &'a HashMap<String, Vec<(Key, Item)>>,
+    defaults: &'a [(Key, Item)],
+    resolved: HashMap<String, Vec<(Key, Item)>>,
+    report: &'a mut Report,
+}
+
+impl<'a> Resolver<'a> {
+    fn resolve(&mut self, name: &str, chain: &mut Vec<String>) -> Vec<(Key, Item)> {
+        if let Some(fields) = self.resolved.get(name) {
+            return fields.clone();
+        }
+        chain.push(name.to_string());
+        let own_entries = self.own;
+        let own = &own_entries[name];
+        let path = format!("{}.{}.extends", self.section, name);
+        let base = match own.iter().find(|(key, _)| key.get() == "extends") {
+            None => self.defaults.to_vec(),
+            Some((_, item)) => match item.as_str() {
+                Some(parent) if chain.iter().any(|n| n == parent) => {
+                    let mut cycle = chain.clone();
+                    cycle.push(parent.to_string());
+                    push(
+                        self.report,
+                        self.source,
+                        Problem::InheritanceCycle(cycle),
+                        &path,
+                        item,
+                    );
+                    self.defaults.to_vec()
+                }
+                Some(parent) if self.own.contains_key(parent) => self.resolve(parent, chain),
+                Some(parent) => {
+                    push(
+                        self.report,
+                        self.source,
+                        Problem::UnknownParent(parent.to_string()),
+                        &path,
+                        item,
+                    );
+                    self.defaults.to_vec()
+                }
+                None => {
+                    let problem =
+                        Problem::Parse("`extends` must be the name of another entry".to_string());
+                    push(self.report, self.source, problem, &path, item);
+                    self.defaults.to_vec()
+                }
+            },
+        };
+        chain.pop();
+
+        let mut merged = base;
+        for (key, item) in own.iter().filter(|(key, _)| key.get() != "extends") {
+            match merged.iter_mut().find(|(k, _)| k.get() == key.get()) {
+                Some(existing) => *existing = (key.clone(), merge(&existing.1, item)),
+                None => merged.push((key.clone(), item.clone())),
+            }
+        }
+        self.resolved.insert(name.to_string(), merged.clone());
+        merged
+    }
+}
+
+/// `own` laid over `base`: where both are tables their keys are merged, in
+/// turn, and otherwise `own` replaces `base`.
+fn merge(base: &Item, own: &Item) -> Item {
+    let mut merged = own.clone();
+    let (base, table) = match (base.as_table_like(), merged.as_table_like_mut()) {
+        (Some(base), Some(table)) => (base, table),
+        _ => return merged,
+    };
+    for (key, item) in base.iter() {
+        match table.get_mut(key) {
+            Some(own) => *own = merge(item, own),
+            None => {
+                let key = base.key(key).unwrap();
+                table.entry_format(key).or_insert(item.clone());
+            }
+        }
+    }
+    merged
+}
+
+fn fields(item: Option<&Item>) -> Vec<(Key, Item)> {
+    let table = match item.and_then(Item::as_table_like) {
+        Some(table) => table,
+        None => return Vec::new(),
+    };
+    table
+        .iter()
+        .filter_map(|(key, _)| table.get_key_value(key))
+        .map(|(key, item)| (key.clone(), item.clone()))
+        .collect()
+}
+
+fn push(report: &mut Report, source: &str, problem: Problem, path: &str, item: &Item) {
+    report.push(Diagnostic {
+        severity: Severity::Error,
+        problem,
+        path: path.to_string(),
+        location: item.span().map(|s| location(source, s.start)),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use toml_edit::ImDocument;
+
+    fn resolved(text: &str) -> (Table, Report) {
+        let mut root = ImDocument::parse(text).unwrap().into_table();
+        let mut report = Report::default();
+        resolve(&mut root, text, &mut report);
+        (root, report)
+    }
+
+    #[test]
+    fn test_extends_chain_and_defaults() {
+        let (root, report) = resolved(
+            r#"
+[defaults.emitter]
+radius = 4
+life_span = 100
+
+[emitters.base]
+noise_scale = 0.005
+flight_size = 10
+
+[emitters.child]
+extends = "grandchild_of_nothing"
+
+[emitters.grandchild_of_nothing]
+extends = "base"
+flight_size = 50
+radius = 2
+"#,
+        );
+        assert!(report.diagnostics.is_empty(), "{}", report);
+        let child = root["emitters"]["child"].as_table().unwrap();
+        assert!(child.get("extends").is_none());
+        assert_eq!(child["radius"].as_integer(), Some(2));
+        assert_eq!(child["life_span"].as_integer(), Some(100));
+        assert_eq!(child["flight_size"].as_integer(), Some(50));
+        assert_eq!(child["noise_scale"].as_float(), Some(0.005));
+        assert_eq!(root["emitters"]["base"]["radius"].as_integer(), Some(4));
+        assert!(root.get("defaults").is_none());
+    }
+
+    #[test]
+    fn test_nested_tables_merge() {
+        let (root, report) = resolved(
+            r#"
+[defaults.emitter.emission]
+mode = "poisson"
+[emitters.base.emission]
+rate = 5
+period = 2
+[emitters.base.forces]
+drag = 0.1
+[emitters.child]
+extends = "base"
+emission = { rate = 8 }
+[emitters.child.forces]
+gravity = [0, -1]
+"#,
+        );
+        assert!(report.diagnostics.is_empty(), "{}", report);
+        let child = &root["emitters"]["child"];
+        assert_eq!(child["emission"]["rate"].as_integer(), Some(8));
+        assert_eq!(child["emission"]["period"].as_integer(), Some(2));
+        assert_eq!(child["emission"]["mode"].as_str(), Some("poisson"));
+        assert_eq!(child["forces"]["drag"].as_float(), Some(0.1));
+        assert!(child["forces"]["gravity"].is_array());
+        let config = crate::config::config_from_table(root.clone(), "", report).unwrap();
+        let emission = config.emitters.unwrap()["child"].emission.clone().unwrap();
+        assert_eq!(emission.period, Some(2.0));
+    }
+
+    #[test]
+    fn test_inherited_values_keep_their_span() {
+        let text = "[movers.a]\nscale = [1, 1]\n[movers.b]\nextends = \"a\"\n";
+        let (root, _) = resolved(text);
+        let span = root["movers"]["b"]["scale"].span().unwrap();
+        assert_eq!(location(text, span.start).line, 2);
+    }
+
+    #[test]
+    fn test_cycles_and_unknown_parents() {
+        let (_, report) = resolved(
+            r#"
+[color_pickers.a]
+extends = "b"
+[color_pickers.b]
+extends = "a"
+[color_pickers.c]
+extends = "nope"
+"#,
+        );
+        let problems: Vec<Problem> = report.errors().map(|d| d.problem.clone()).collect();
+        assert_eq!(
+            problems,
+            vec![
+                Problem::InheritanceCycle(vec!["a".into(), "b".into(), "a".into()]),
+                Problem::UnknownParent("nope".into()),
+            ]
+        );
+        assert_eq!(report.diagnostics[0].location.unwrap().line, 5);
+    }
+}
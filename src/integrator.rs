@@ -0,0 +1,126 @@
+use nannou::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// How a particle's position and velocity advance over one step. Steps are
+/// measured in ticks, where a tick is a 60th of a second, the frame time the
+/// config's speeds, forces and life spans have always been tuned for.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Integrator {
+    /// Moves with the old velocity, then updates it.
+    ExplicitEuler,
+    /// Updates the velocity, then moves with it. What the sketch has always done.
+    #[default]
+    SemiImplicitEuler,
+    VelocityVerlet,
+    /// Classic fourth order Runge-Kutta.
+    Rk4,
+}
+
+impl Integrator {
+    /// Advances `position` and `velocity` by `h` ticks under `acceleration`,
+    /// which is evaluated at a position and velocity.
+    pub fn step(
+        self,
+        position: Vec2,
+        velocity: Vec2,
+        h: f32,
+        acceleration: impl Fn(Vec2, Vec2) -> Vec2,
+    ) -> (Vec2, Vec2) {
+        match self {
+            Integrator::ExplicitEuler => {
+                let a = acceleration(position, velocity);
+                (position + velocity * h, velocity + a * h)
+            }
+            Integrator::SemiImplicitEuler => {
+                let velocity = velocity + acceleration(position, velocity) * h;
+                (position + velocity * h, velocity)
+            }
+            Integrator::VelocityVerlet => {
+                let a0 = acceleration(position, velocity);
+                let next = position + velocity * h + a0 * (0.5 * h * h);
+                let a1 = acceleration(next, velocity + a0 * h);
+                (next, velocity + (a0 + a1) * (0.5 * h))
+            }
+            Integrator::Rk4 => {
+                let (x1, v1) = (velocity, acceleration(position, velocity));
+                let (x2, v2) = {
+                    let v = velocity + v1 * (0.5 * h);
+                    (v, acceleration(position + x1 * (0.5 * h), v))
+                };
+                let (x3, v3) = {
+                    let v = velocity + v2 * (0.5 * h);
+                    (v, acceleration(position + x2 * (0.5 * h), v))
+                };
+                let (x4, v4) = {
+                    let v = velocity + v3 * h;
+                    (v, acceleration(position + x3 * h, v))
+                };
+                (
+                    position + (x1 + x2 * 2.0 + x3 * 2.0 + x4) * (h / 6.0),
+                    velocity + (v1 + v2 * 2.0 + v3 * 2.0 + v4) * (h / 6.0),
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Integrator; 4] = [
+        Integrator::ExplicitEuler,
+        Integrator::SemiImplicitEuler,
+        Integrator::VelocityVerlet,
+        Integrator::Rk4,
+    ];
+
+    /// Where each integrator puts a body falling under constant gravity after
+    /// 60 ticks taken `steps` at a time.
+    fn fall(integrator: Integrator, steps: u32) -> Vec2 {
+        let h = 60.0 / steps as f32;
+        let (mut position, mut velocity) = (vec2(0.0, 0.0), vec2(1.0, 0.0));
+        for _ in 0..steps {
+            (position, velocity) = integrator.step(position, velocity, h, |_, _| vec2(0.0, -0.01));
+        }
+        position
+    }
+
+    #[test]
+    fn test_constant_acceleration() {
+        // Exactly x = 60, y = -0.01 * 60^2 / 2 = -18.
+        for integrator in [Integrator::VelocityVerlet, Integrator::Rk4] {
+            for steps in [30, 60, 144] {
+                let p = fall(integrator, steps);
+                assert!((p - vec2(60.0, -18.0)).length() < 1e-3, "{:?}", integrator);
+            }
+        }
+        // The Euler variants are off by half a step either way.
+        assert!((fall(Integrator::ExplicitEuler, 60).y + 17.7).abs() < 1e-3);
+        assert!((fall(Integrator::SemiImplicitEuler, 60).y + 18.3).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_spring_converges() {
+        // A unit spring started at x = 1 is at cos(t) after t ticks.
+        for integrator in ALL {
+            let (mut position, mut velocity) = (vec2(1.0, 0.0), vec2(0.0, 0.0));
+            for _ in 0..1000 {
+                (position, velocity) = integrator.step(position, velocity, 0.001, |p, _| -p);
+            }
+            assert!((position.x - 1f32.cos()).abs() < 1e-3, "{:?}", integrator);
+        }
+    }
+
+    #[test]
+    fn test_config_names() {
+        #[derive(Deserialize)]
+        struct Wrapper {
+            integrator: Integrator,
+        }
+        let w: Wrapper = toml::from_str("integrator = \"velocity_verlet\"").unwrap();
+        assert_eq!(w.integrator, Integrator::VelocityVerlet);
+        assert!(toml::from_str::<Wrapper>("integrator = \"leapfrog\"").is_err());
+    }
+}
@@ -1,6 +1,25 @@
+pub mod attractor;
+pub mod boundary;
 pub mod color_picker;
 pub mod config;
+pub mod emission;
 pub mod emitter;
+pub mod expression;
+pub mod field;
+pub mod forces;
+pub mod format;
+pub mod inheritance;
+pub mod integrator;
+pub mod migration;
 pub mod particle;
 pub mod mover;
+pub mod rng;
+pub mod scene;
+pub mod session;
+pub mod snapshot;
+pub mod stats;
+pub mod sweep;
 
+pub mod validation;
+pub mod watcher;
+pub mod world;
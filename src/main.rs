@@ -9,7 +9,6 @@ use particle_lib::*;
 lazy_static! {
     #[derive(Debug)]
     pub static ref OPT: Opt = Opt::from_args();
-    pub static ref CONFIG: config::Config = config::read_config(&OPT.config_file);
 }
 
 #[derive(StructOpt, Debug)]
@@ -18,28 +17,175 @@ pub struct Opt {
     /// Configuration file
     #[structopt(short, long, default_value = "")]
     config_file: String,   
+
+    /// Config file format (toml, json, yaml or ron); guessed from the extension by default
+    #[structopt(long)]
+    format: Option<format::Format>,
+
+    /// Override a config value, e.g. `--set emitters.rainbow.radius=6`; repeatable
+    #[structopt(long = "set", number_of_values = 1)]
+    overrides: Vec<String>,
+
+    /// Carry live particles over to same-named emitters when the config file is reloaded
+    #[structopt(long)]
+    keep_particles: bool,
+
+    /// Keep drawing on the existing canvas when the config file is reloaded
+    #[structopt(long)]
+    keep_canvas: bool,
+
+    /// Print the effective configuration, with every default filled in, and exit
+    #[structopt(long)]
+    print_config: bool,
+
+    /// Run and capture every variant of the config's [sweep] section, then exit
+    #[structopt(long, conflicts_with_all = &["resume", "record", "replay"])]
+    sweep: bool,
+
+    /// Resume from a snapshot saved with W; edits to the config file then branch from it
+    #[structopt(long)]
+    resume: Option<String>,
+
+    /// Log key presses and config reloads, with their frames, to this file
+    #[structopt(long)]
+    record: Option<String>,
+
+    /// Replay a session logged with --record, frame by frame, then exit
+    #[structopt(long, conflicts_with = "resume")]
+    replay: Option<String>,
+
+    /// Draw at this multiple of the config's window size, e.g. to re-render a replay in high resolution
+    #[structopt(long, default_value = "1.0")]
+    scale: f32,
+
+    /// Write per-step particle stats to this file, as CSV if it ends in .csv and JSON lines otherwise
+    #[structopt(long)]
+    stats: Option<String>,
+
+    /// Draw a stats readout over the sketch; D toggles it
+    #[structopt(long)]
+    show_stats: bool,
 }
 
 struct Model {
-    emitters: Vec<emitter::Emitter>,
+    world: world::World,
+    watcher: Option<watcher::ConfigWatcher>,
+    clear_canvas: bool,
+    sweep: Option<SweepRun>,
+    recorder: Option<session::Recorder>,
+    replay: Option<session::Replay>,
+    stats_writer: Option<stats::StatsWriter>,
+    show_stats: bool,
+}
+
+struct SweepRun {
+    sweep: sweep::Sweep,
+    current: usize,
+    captured: bool,
+}
+
+fn read_config_or_exit(config_file: &str) -> config::Config {
+    match config::read_config(config_file, OPT.format, &OPT.overrides) {
+        Ok(config) => config.with_defaults(),
+        Err(report) => {
+            eprintln!("Invalid config file {:?}:\n{}", config_file, report);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn read_snapshot_or_exit(path: &str) -> snapshot::Snapshot {
+    match snapshot::Snapshot::load(path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            eprintln!("Unable to read snapshot {:?}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn read_replay_or_exit(path: &str) -> session::Replay {
+    match session::Replay::load(path) {
+        Ok(replay) => replay,
+        Err(e) => {
+            eprintln!("Unable to read session log {:?}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn create_recorder_or_exit(path: &str) -> session::Recorder {
+    match session::Recorder::create(path) {
+        Ok(recorder) => recorder,
+        Err(e) => {
+            eprintln!("Unable to create session log {:?}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn create_stats_writer_or_exit(path: &str) -> stats::StatsWriter {
+    match stats::StatsWriter::create(path) {
+        Ok(writer) => writer,
+        Err(e) => {
+            eprintln!("Unable to create stats file {:?}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn expand_sweep_or_exit(config_file: &str) -> sweep::Sweep {
+    let (contents, format) = config::read_source(config_file, OPT.format);
+    match sweep::expand(&contents, format, &OPT.overrides) {
+        Ok(sweep) if !sweep.variants.is_empty() => sweep,
+        Ok(_) => {
+            eprintln!("Config file {:?} has no [sweep] values", config_file);
+            std::process::exit(1);
+        }
+        Err(report) => {
+            eprintln!("Invalid sweep in {:?}:\n{}", config_file, report);
+            std::process::exit(1);
+        }
+    }
 }
 
 fn model(_app: &App) -> Model {
-    let default_window_size : f32 = 600.;
-    let window_height = CONFIG.window_height.as_ref().unwrap_or(&default_window_size);
-    let window_width =  CONFIG.window_width.as_ref().unwrap_or(&default_window_size); 
+    let sweep = if OPT.sweep {
+        let sweep = expand_sweep_or_exit(&OPT.config_file);
+        info!("Sweeping {} variants", sweep.variants.len());
+        Some(SweepRun {
+            sweep,
+            current: 0,
+            captured: false,
+        })
+    } else {
+        None
+    };
+    let replay = OPT.replay.as_deref().map(read_replay_or_exit);
+    let snapshot = match replay {
+        Some(ref replay) => Some(replay.start().clone()),
+        None => OPT.resume.as_deref().map(read_snapshot_or_exit),
+    };
+    let config = match (&sweep, &snapshot) {
+        (Some(run), _) => run.sweep.variants[0].config.clone().with_defaults(),
+        (None, Some(snapshot)) => snapshot.config.clone(),
+        (None, None) => read_config_or_exit(&OPT.config_file),
+    };
+    let window_height = config.window_height.unwrap();
+    let window_width = config.window_width.unwrap();
     _app.new_window()
-        .size(window_width.clone() as u32, window_height.clone() as u32)  
+        .size((window_width * OPT.scale) as u32, (window_height * OPT.scale) as u32)
         .key_released(key_released)
         .view(view)
         .build()
         .unwrap();
 
-    let r = _app.window_rect().right();
-    let l = _app.window_rect().left();
+    // The simulation keeps the config's size however large it's drawn.
+    let r = _app.window_rect().right() / OPT.scale;
+    let l = _app.window_rect().left() / OPT.scale;
 
-    let t = _app.window_rect().top();
-    let b = _app.window_rect().bottom();
+    let t = _app.window_rect().top() / OPT.scale;
+    let b = _app.window_rect().bottom() / OPT.scale;
     let bounds: emitter::Bounds = emitter::Bounds {
         top: t,
         bottom: b,
@@ -47,90 +193,304 @@ fn model(_app: &App) -> Model {
         right: r,
     };
 
-    let selected_emitters = match CONFIG.selected_emitters {
-        Some(ref emitters) => emitters.clone(),
-        None => vec!["default".to_string()],
-    };
-    let color_pickers = match CONFIG.color_pickers {
-        Some(ref color_pickers) => color_pickers.clone(),
-        None => std::collections::HashMap::new(),
+    let watcher = if OPT.config_file.is_empty() || sweep.is_some() || replay.is_some() {
+        None
+    } else {
+        let format = OPT
+            .format
+            .or_else(|| format::Format::from_path(&OPT.config_file))
+            .unwrap_or(format::Format::Toml);
+        Some(watcher::ConfigWatcher::new(
+            &OPT.config_file,
+            format,
+            OPT.overrides.clone(),
+            std::time::Duration::from_millis(500),
+        ))
     };
-    let movers = match CONFIG.movers {
-        Some(ref movers) => movers.clone(),
-        None => std::collections::HashMap::new(),
+
+    let mut world = match snapshot {
+        Some(snapshot) if sweep.is_none() => world::World::from_snapshot(snapshot, bounds),
+        _ => world::World::new(&config, bounds),
     };
-    let seed = CONFIG.seed.unwrap_or(0);
-    let mut emitters = Vec::new();
-    for e in selected_emitters.iter() {
-        info!("emitter: {:?}", e);
-        let emitter = emitter::Emitter::from_config(
-            e.to_string(),
-            CONFIG.emitters.as_ref().unwrap()[e].clone(),
-            &color_pickers,
-            &movers,
-            bounds,
-            seed,
-        );  
-        emitters.push(emitter); 
+
+    let stats_writer = OPT.stats.as_deref().map(create_stats_writer_or_exit);
+    world.collect_stats(stats_writer.is_some());
+
+    let mut recorder = OPT.record.as_deref().map(create_recorder_or_exit);
+    if let Some(ref mut recorder) = recorder {
+        let start = session::Event::Start {
+            snapshot: Box::new(world.snapshot()),
+        };
+        if let Err(e) = recorder.record(world.frame(), &start) {
+            error!("Unable to record session: {}", e);
+        }
     }
 
+    Model {
+        world,
+        watcher,
+        clear_canvas: false,
+        sweep,
+        recorder,
+        replay,
+        stats_writer,
+        show_stats: OPT.show_stats,
+    }
+}
 
-    Model { emitters }
+/// Logs `event` as happening before `frame`, if the session is being recorded.
+fn record(_model: &mut Model, frame: u64, event: &session::Event) {
+    if let Some(ref mut recorder) = _model.recorder {
+        if let Err(e) = recorder.record(frame, event) {
+            error!("Unable to record session: {}", e);
+        }
+    }
 }
 
-fn update(_app: &App, _model: &mut Model, _update: Update) {
-    let _t = _app.elapsed_frames() as f32 / 360.;
-    for e in _model.emitters.iter_mut() {
-        e.update(_t);
+/// Records `event` if the session is being recorded, then carries it out.
+fn apply_event(_app: &App, _model: &mut Model, event: session::Event) {
+    let frame = _model.world.frame();
+    record(_model, frame, &event);
+    match event {
+        session::Event::TogglePause => info!("Toggling pause"),
+        session::Event::Capture => {
+            let file_path = if _model.replay.is_some() {
+                replay_frame_path(_app, frame)
+            } else {
+                captured_frame_path(_app)
+            };
+            info!("Capturing frame to {:?}", file_path);
+            _app.main_window().capture_frame(file_path);
+        }
+        session::Event::SaveSnapshot => {
+            let file_path = snapshot_path(_app, frame);
+            info!("Saving snapshot to {:?}", file_path);
+            if let Err(e) = _model.world.snapshot().save(&file_path) {
+                error!("Unable to save snapshot {:?}: {}", file_path, e);
+            }
+        }
+        session::Event::Load { keep_canvas, .. } => _model.clear_canvas = !keep_canvas,
+        _ => {}
     }
-    for e in _model.emitters.iter_mut() {
-        if random_f32() > 0.9 {
-            e.emit();
-            
-            // conditionally apply force that changes over time
-            // e.apply_force(vec2(0. * 0.004, -1. * 0.002));
-            // e.apply_force(vec2(_t.cos() * 0.005, _t.sin() * 0.005));
-            // e.apply_force(vec2(-1. * 0.004, 1. * 0.002));
+    _model.world.apply(&event);
+}
+
+fn reload(_app: &App, _model: &mut Model) {
+    let result = match _model.watcher.as_mut().and_then(|w| w.poll()) {
+        Some(result) => result,
+        None => return,
+    };
+    let config = match result {
+        Ok(config) => config.with_defaults(),
+        Err(report) => {
+            error!("Not reloading {:?}:\n{}", OPT.config_file, report);
+            return;
         }
+    };
+    let event = session::Event::Load {
+        config: Box::new(config),
+        keep_particles: OPT.keep_particles,
+        keep_canvas: OPT.keep_canvas,
+    };
+    apply_event(_app, _model, event);
+    info!("Reloaded {:?}", OPT.config_file);
+}
+
+/// Moves a sweep on to its next variant once the last capture has been drawn,
+/// and asks for a capture once a variant reaches its step count. Returns
+/// whether to take a step: not while a variant waits for its capture, so the
+/// image shows the world at exactly that count.
+fn advance_sweep(_app: &App, _model: &mut Model) -> bool {
+    let run = match _model.sweep.as_mut() {
+        Some(run) => run,
+        None => return false,
+    };
+    if run.captured {
+        // The capture was requested on the previous frame; move on.
+        run.current += 1;
+        run.captured = false;
+        match run.sweep.variants.get(run.current) {
+            Some(variant) => {
+                info!("Sweep variant {}: {}", run.current, variant.label);
+                _model.world = world::World::new(&variant.config, _model.world.bounds());
+                _model.world.collect_stats(_model.stats_writer.is_some());
+                _model.clear_canvas = true;
+            }
+            None => {
+                _app.quit();
+                return false;
+            }
+        }
+    }
+    if _model.world.frame() >= run.sweep.frames {
+        let variant = &run.sweep.variants[run.current];
+        let file_path = sweep_frame_path(_app, &variant.file_name(run.current));
+        info!("Capturing sweep variant to {:?}", file_path);
+        _app.main_window().capture_frame(file_path);
+        run.captured = true;
+        return false;
+    }
+    true
+}
+
+/// Carries out the replayed events of the next rendered frame, taking as many
+/// steps as the session took on that frame.
+fn advance_replay(_app: &App, _model: &mut Model) {
+    let events = match _model.replay.as_mut() {
+        Some(replay) if replay.is_finished() => {
+            // A log cut short without an end event stops after its last
+            // event, which was on the previous frame, so any capture it asked
+            // for has been drawn.
+            info!("Replay finished");
+            _app.quit();
+            return;
+        }
+        Some(replay) => replay.next_frame(),
+        None => return,
+    };
+    for event in events {
+        if event == session::Event::End {
+            // The frame the session ended on has been drawn.
+            info!("Replay finished");
+            _app.quit();
+            return;
+        }
+        apply_event(_app, _model, event);
+    }
+}
+
+fn update(_app: &App, _model: &mut Model, _update: Update) {
+    _model.clear_canvas = false;
+    reload(_app, _model);
+    if _model.replay.is_some() {
+        // Replays take the logged steps each frame, so every frame is drawn as
+        // it was live.
+        advance_replay(_app, _model);
+    } else {
+        let frame = _model.world.frame();
+        let steps = if _model.sweep.is_some() {
+            // Sweeps are captured at a set step count, however long frames take.
+            let step = advance_sweep(_app, _model);
+            if step {
+                _model.world.step();
+            }
+            step as u32
+        } else {
+            _model.world.advance(_update.since_last.as_secs_f64())
+        };
+        record(_model, frame, &session::Event::Frame { steps });
+    }
+    write_stats(_model);
+}
+
+fn write_stats(_model: &mut Model) {
+    let writer = match _model.stats_writer.as_mut() {
+        Some(writer) => writer,
+        None => return,
+    };
+    let result = _model
+        .world
+        .take_stats()
+        .iter()
+        .try_for_each(|stats| writer.write(stats))
+        .and_then(|_| writer.flush());
+    if let Err(e) = result {
+        error!("Unable to write stats, no longer writing them: {}", e);
+        _model.stats_writer = None;
+        _model.world.collect_stats(false);
     }
 }
 
 fn view(_app: &App, _model: &Model, frame: Frame) {
     // Begin drawing
-    let draw = _app.draw();
+    let draw = _app.draw().scale(OPT.scale);
 
-    if _app.elapsed_frames() == 0 {
+    if _app.elapsed_frames() == 0 || _model.clear_canvas {
         draw.background().color(BLACK);
     }
 
     // Draw the emitters
-    for e in _model.emitters.iter() {
-        e.display(&draw);
+    _model.world.display(&draw);
+
+    if _model.show_stats {
+        draw_stats(_model, &draw);
     }
 
     // Write the result of our drawing to the window's frame.
     draw.to_frame(_app, &frame).unwrap();
 }
 
+/// Writes the latest stats in the top left corner. The canvas is never cleared,
+/// so the readout gets a black box of its own and shows up in captures.
+fn draw_stats(_model: &Model, draw: &Draw) {
+    let summary = _model.world.stats().summary();
+    let bounds = _model.world.bounds();
+    let (w, h) = (320.0, 14.0 * summary.lines().count() as f32 + 8.0);
+    let (x, y) = (bounds.left + 10.0 + w / 2.0, bounds.top - 10.0 - h / 2.0);
+    draw.rect().x_y(x, y).w_h(w, h).color(BLACK);
+    draw.text(&summary)
+        .x_y(x + 4.0, y - 4.0)
+        .w_h(w, h)
+        .font_size(11)
+        .left_justify()
+        .align_text_top()
+        .color(WHITE);
+}
+
 fn key_released(_app: &App, _model: &mut Model, key: Key) {
     trace!("{:?}", key);
-    match key {
-        Key::Space => {
-            info!("Toggling pause");
-            for e in _model.emitters.iter_mut() {
-                e.toggle_pause();
-            }
-        }
-        Key::S => {
-            let file_path = captured_frame_path(_app);
-            info!("Capturing frame to {:?}", file_path);
-            _app.main_window().capture_frame(file_path);
-        }
+    let event = match key {
+        Key::Space => session::Event::TogglePause,
+        Key::S => session::Event::Capture,
         Key::M => {
             info!("Mouse Position: {:#?}", _app.mouse.position());
+            return;
         }
-        _ => {}
+        Key::D => {
+            _model.show_stats = !_model.show_stats;
+            return;
+        }
+        Key::W => session::Event::SaveSnapshot,
+        Key::N => session::Event::NextScene,
+        Key::P => session::Event::PreviousScene,
+        _ => return,
+    };
+    if _model.replay.is_some() {
+        info!("Ignoring {:?} during a replay", key);
+        return;
     }
+    apply_event(_app, _model, event);
+}
+
+/// Ends the session log, if there is one, where the sketch stopped.
+fn exit(_app: &App, model: Model) {
+    if let Some(mut recorder) = model.recorder {
+        if let Err(e) = recorder.record(model.world.frame(), &session::Event::End) {
+            error!("Unable to record session: {}", e);
+        }
+    }
+}
+
+fn sweep_frame_path(_app: &App, file_name: &str) -> std::path::PathBuf {
+    // Sweep labels contain dots, so the extension is appended rather than set.
+    _app.project_path()
+        .expect("failed to locate `project_path`")
+        .join("frames")
+        .join(format!("{}.png", file_name))
+}
+
+fn snapshot_path(_app: &App, frame: u64) -> std::path::PathBuf {
+    _app.project_path()
+        .expect("failed to locate `project_path`")
+        .join("snapshots")
+        .join(format!("frame_{:06}.json", frame))
+}
+
+fn replay_frame_path(_app: &App, frame: u64) -> std::path::PathBuf {
+    _app.project_path()
+        .expect("failed to locate `project_path`")
+        .join("frames")
+        .join(format!("replay_{:06}.png", frame))
 }
 
 fn captured_frame_path(_app: &App) -> std::path::PathBuf {
@@ -143,6 +503,14 @@ fn captured_frame_path(_app: &App) -> std::path::PathBuf {
 
 fn main() {
     pretty_env_logger::init();
+    if OPT.print_config {
+        let config = read_config_or_exit(&OPT.config_file);
+        match config::to_toml(&config) {
+            Ok(text) => print!("{}", text),
+            Err(e) => eprintln!("Unable to print config: {}", e),
+        }
+        return;
+    }
     // info!("{:?}", *OPT);
-    nannou::app(model).update(update).run();
+    nannou::app(model).update(update).exit(exit).run();
 }
@@ -0,0 +1,121 @@
+use super::validation::{location, Diagnostic, Problem, Report, Severity};
+
+use toml_edit::{Item, Table};
+
+/// Current version of the config schema. Files without a `version` key are
+/// treated as version 1.
+pub const CONFIG_VERSION: i64 = 2;
+
+/// A migration step rewrites a document from version `n` to `n + 1` and returns
+/// a note (with the dotted path it applies to) for every change it made.
+type Migration = fn(&mut Table) -> Vec<(String, String)>;
+
+const MIGRATIONS: &[Migration] = &[v1_to_v2];
+
+/// Brings `root` up to `CONFIG_VERSION`, recording each change as a warning in
+/// `report`. Moved values keep their spans into `source`.
+pub fn migrate(root: &mut Table, source: &str, report: &mut Report) {
+    let version = match root.get("version") {
+        Some(item) => match item.as_integer() {
+            Some(version) => version,
+            // Leave type errors to deserialization, which knows the span.
+            None => return,
+        },
+        None => 1,
+    };
+    if version > CONFIG_VERSION {
+        let span = root.get("version").and_then(Item::span);
+        report.push(Diagnostic {
+            severity: Severity::Error,
+            problem: Problem::UnsupportedVersion(version),
+            path: "version".to_string(),
+            location: span.map(|span| location(source, span.start)),
+        });
+        return;
+    }
+
+    for migration in MIGRATIONS.iter().skip((version.max(1) - 1) as usize) {
+        for (path, note) in migration(root) {
+            report.push(Diagnostic {
+                severity: Severity::Warning,
+                problem: Problem::Migrated(note),
+                path,
+                location: None,
+            });
+        }
+    }
+}
+
+/// Version 1 silently ignored an emitter's `position`; it is now `origin`.
+fn v1_to_v2(root: &mut Table) -> Vec<(String, String)> {
+    let mut notes = Vec::new();
+    let emitters = match root.get_mut("emitters").and_then(Item::as_table_like_mut) {
+        Some(emitters) => emitters,
+        None => return notes,
+    };
+    for (name, emitter) in emitters.iter_mut() {
+        let emitter = match emitter.as_table_like_mut() {
+            Some(emitter) => emitter,
+            None => continue,
+        };
+        let position = match emitter.remove("position") {
+            Some(position) => position,
+            None => continue,
+        };
+        let path = format!("emitters.{}.position", name.get());
+        if emitter.contains_key("origin") {
+            notes.push((path, "dropped `position` in favour of `origin`".to_string()));
+        } else {
+            emitter.insert("origin", position);
+            notes.push((path, "renamed `position` to `origin`".to_string()));
+        }
+    }
+    notes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use toml_edit::ImDocument;
+
+    fn migrated(text: &str) -> (Table, Report) {
+        let mut root = ImDocument::parse(text).unwrap().into_table();
+        let mut report = Report::default();
+        migrate(&mut root, text, &mut report);
+        (root, report)
+    }
+
+    #[test]
+    fn test_current_version_is_untouched() {
+        let (root, report) = migrated("version = 2\n[emitters.e]\nposition = [1, 2]\n");
+        assert!(root["emitters"]["e"].get("position").is_some());
+        assert!(report.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_v1_position() {
+        let text = r#"
+[emitters.a]
+position = [1, 2]
+[emitters.b]
+position = [3, 4]
+origin = [5, 6]
+"#;
+        let (root, report) = migrated(text);
+        assert!(root["emitters"]["a"].get("position").is_none());
+        let origin = &root["emitters"]["a"]["origin"];
+        assert_eq!(origin.to_string().trim(), "[1, 2]");
+        assert_eq!(location(text, origin.span().unwrap().start).line, 3);
+        assert_eq!(root["emitters"]["b"]["origin"].to_string().trim(), "[5, 6]");
+        assert_eq!(report.warnings().count(), 2);
+    }
+
+    #[test]
+    fn test_future_version() {
+        let (_, report) = migrated("version = 99\n");
+        assert_eq!(
+            report.diagnostics[0].problem,
+            Problem::UnsupportedVersion(99)
+        );
+    }
+}
@@ -1,9 +1,11 @@
 use super::config::MoverConfig;
+use super::expression::Param;
 use nannou::prelude::*;
+use serde::{Deserialize, Serialize};
 
 fn dot(a: Vec2, b: Vec2) -> Vec2 {
     vec2(a.x * b.x, a.y * b.y)
-} 
+}
 
 #[derive(Debug, Clone, Copy)]
 pub struct MoverParams {
@@ -16,11 +18,26 @@ pub struct MoverParams {
 }
 pub type MoverFn = fn(f32, MoverParams) -> Point2;
 
+/// Names accepted for `mover_type` in a `MoverConfig`.
+pub const MOVER_TYPES: &[&str] = &["p_elipse"];
+
+/// The parts of a `Mover` that build up as it runs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct MoverState {
+    /// Radians turned so far at `rotation_speed`.
+    pub spin: f32,
+    /// Sketch time of the last update, if there has been one.
+    pub t: Option<f32>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Mover {
     pub name: String,
     pub params: MoverParams,
     pub mover: MoverFn,
+    rotation_angle: Param,
+    rotation_speed: Param,
+    state: MoverState,
 }
 
 impl Mover {
@@ -29,26 +46,54 @@ impl Mover {
             "p_elipse" => p_elipse,
             _ => p_elipse,
         };
+        let config = config.with_defaults();
+        let rotation_angle = Param::from_config(&config.rotation_angle.unwrap());
+        let rotation_speed = Param::from_config(&config.rotation_speed.unwrap());
         let params = MoverParams {
             inner: config.inner,
             outer: config.outer,
             scale: config.scale,
-            translation: config.translation.unwrap_or(vec2(0., 0.)),
-            rotation_angle: config.rotation_angle.unwrap_or(0.),
-            rotation_speed: config.rotation_speed.unwrap_or(0.),
+            translation: config.translation.unwrap(),
+            rotation_angle: rotation_angle.value() as f32,
+            rotation_speed: rotation_speed.value() as f32,
         };
         Mover {
             name,
             params,
             mover,
+            rotation_angle,
+            rotation_speed,
+            state: MoverState::default(),
         }
     }
 
+    /// Re-evaluates the rotation parameters that vary over time, then turns
+    /// the mover at its new speed for the time since the last update.
+    pub fn update(&mut self, t: f32, frame: u64) {
+        self.rotation_angle.update(t, frame);
+        self.rotation_speed.update(t, frame);
+        self.params.rotation_angle = self.rotation_angle.value() as f32;
+        self.params.rotation_speed = self.rotation_speed.value() as f32;
+        // A mover built mid-run, e.g. by a reload, starts where it would be
+        // had it always turned at its current speed.
+        let since = t - self.state.t.unwrap_or(0.0);
+        self.state.spin += self.params.rotation_speed * since;
+        self.state.t = Some(t);
+    }
+
     pub fn get_postion(&self, t: f32) -> Point2 {
         let position = (self.mover)(t, self.params)
-            .rotate(self.params.rotation_angle + t * self.params.rotation_speed);
+            .rotate(self.params.rotation_angle + self.state.spin);
         position + self.params.translation
     }
+
+    pub fn state(&self) -> MoverState {
+        self.state
+    }
+
+    pub fn restore(&mut self, state: MoverState) {
+        self.state = state;
+    }
 }
 
 pub fn p_elipse(t:f32, params: MoverParams) -> Point2 {
@@ -75,10 +120,36 @@ mod tests {
             scale: vec2(50., 100.),
             translation: vec2(0., 0.),
             rotation_angle: 0.0,
-            rotation_speed: 0.0, 
+            rotation_speed: 0.0,
         });
         assert_eq!(p, pt2(25.0, 0.0));
     }
+
+    #[test]
+    fn test_rotation_speed_accumulates() {
+        let config: MoverConfig = toml::from_str(
+            r#"
+mover_type = "p_elipse"
+inner = [0, 0]
+outer = [1, 0]
+scale = [100, 100]
+rotation_speed = "max(t, 1)"
+"#,
+        )
+        .unwrap();
+        let mut mover = Mover::from_config("m".to_string(), config);
+        let mut angle = 0.0;
+        for step in 0..=20 {
+            let t = step as f32 * 0.1;
+            if step > 0 {
+                // Each step turns the mover at its speed at the end of the step.
+                angle += t.max(1.0) * 0.1;
+            }
+            mover.update(t, step);
+            let p = mover.get_postion(t);
+            assert!((p.y.atan2(p.x) - angle).abs() < 1e-4, "step {}", step);
+        }
+    }
 }
 
 // --- Experimental code below here. Not yet set up for emitters ---
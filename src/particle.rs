@@ -1,7 +1,18 @@
+use super::integrator::Integrator;
+use super::snapshot;
+
 use nannou::prelude::*;
 use nannou::Draw;
+use serde::{Deserialize, Serialize};
+
+/// Life span a particle loses per tick (a 60th of a second).
+pub const AGING_PER_TICK: f32 = 2.0;
+
+/// Fewest particles handed to one thread with the `parallel` feature.
+#[cfg(feature = "parallel")]
+const PARALLEL_MIN_LEN: usize = 1024;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
 pub struct Particle {
     pub position: Vec2,
     pub velocity: Vec2,
@@ -10,6 +21,7 @@ pub struct Particle {
     pub stroke_weight: f32,
     pub life_span: f32,
     pub init_life_span: f32,
+    #[serde(with = "snapshot::hsla")]
     pub color: Hsla,
 }
 
@@ -45,29 +57,38 @@ impl Particle {
         self.acceleration += force;
     }
 
-    // Method to update position
-    pub fn update(&mut self, direction: Option<Vec2>) {
-        match direction {
-            Some(dir) => {
-                self.velocity += dir;
-            }
-            None => {}
-        }
-        self.velocity += self.acceleration;
-        self.position += self.velocity;
-        self.life_span -= 2.0;
+    // Advance `h` ticks; `field` adds an acceleration that depends on position
+    // and velocity, such as a flow field's or drag, to the forces applied since
+    // the last update, which are then cleared
+    pub fn update(
+        &mut self,
+        h: f32,
+        integrator: Integrator,
+        field: impl Fn(Vec2, Vec2) -> Vec2,
+    ) {
+        let acceleration = self.acceleration;
+        (self.position, self.velocity) =
+            integrator.step(self.position, self.velocity, h, |p, v| acceleration + field(p, v));
+        self.acceleration = vec2(0.0, 0.0);
+        self.life_span -= AGING_PER_TICK * h;
     }
 
     // Method to display
     pub fn display(&self, draw: &Draw) {
+        self.display_faded(draw, 1.0);
+    }
+
+    // Display with the alpha scaled by `opacity`, for emitters fading in or out
+    pub fn display_faded(&self, draw: &Draw, opacity: f32) {
         let r = self.radius * self.life_span / self.init_life_span;
+        let alpha = opacity * self.life_span / self.init_life_span;
         let mut color = self.color;
-        color.alpha = self.life_span / self.init_life_span;
+        color.alpha = alpha;
         draw.ellipse()
             .xy(self.position)
             .w_h(r, r)
             .color(color)
-            .stroke(rgba(0.0, 0.0, 0.0, self.life_span / self.init_life_span))
+            .stroke(rgba(0.0, 0.0, 0.0, alpha))
             .stroke_weight(self.stroke_weight);
         // self.draw_velocity(draw);
     }
@@ -89,9 +110,187 @@ impl Particle {
     }
 }
 
+/// An emitter's particles, stored a field at a time so that updating them
+/// touches only the arrays it needs. Dead particles are swapped out in place, so
+/// the pool stays dense and its order changes as particles die.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(from = "Vec<Particle>", into = "Vec<Particle>")]
+pub struct ParticlePool {
+    position: Vec<Vec2>,
+    velocity: Vec<Vec2>,
+    acceleration: Vec<Vec2>,
+    radius: Vec<f32>,
+    stroke_weight: Vec<f32>,
+    life_span: Vec<f32>,
+    init_life_span: Vec<f32>,
+    color: Vec<Hsla>,
+}
+
+impl ParticlePool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.position.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position.is_empty()
+    }
+
+    pub fn push(&mut self, p: Particle) {
+        self.position.push(p.position);
+        self.velocity.push(p.velocity);
+        self.acceleration.push(p.acceleration);
+        self.radius.push(p.radius);
+        self.stroke_weight.push(p.stroke_weight);
+        self.life_span.push(p.life_span);
+        self.init_life_span.push(p.init_life_span);
+        self.color.push(p.color);
+    }
+
+    pub fn get(&self, i: usize) -> Particle {
+        Particle {
+            position: self.position[i],
+            velocity: self.velocity[i],
+            acceleration: self.acceleration[i],
+            radius: self.radius[i],
+            stroke_weight: self.stroke_weight[i],
+            life_span: self.life_span[i],
+            init_life_span: self.init_life_span[i],
+            color: self.color[i],
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Particle> + '_ {
+        (0..self.len()).map(|i| self.get(i))
+    }
+
+    pub fn positions(&self) -> &[Vec2] {
+        &self.position
+    }
+
+    pub fn velocities(&self) -> &[Vec2] {
+        &self.velocity
+    }
+
+    pub fn apply_force(&mut self, force: Vec2) {
+        for a in self.acceleration.iter_mut() {
+            *a += force;
+        }
+    }
+
+    // Advance every particle `h` ticks, as `Particle::update` does, then drop the
+    // dead and return how many there were. With the `parallel` feature particles
+    // are spread over threads; each one's update only reads its own state, so the
+    // result is the same either way.
+    pub fn update(
+        &mut self,
+        h: f32,
+        integrator: Integrator,
+        field: impl Fn(Vec2, Vec2) -> Vec2 + Sync,
+    ) -> usize {
+        let step = |position: &mut Vec2, velocity: &mut Vec2, acceleration: Vec2| {
+            (*position, *velocity) =
+                integrator.step(*position, *velocity, h, |p, v| acceleration + field(p, v));
+        };
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::prelude::*;
+            self.position
+                .par_iter_mut()
+                .zip(self.velocity.par_iter_mut())
+                .zip(self.acceleration.par_iter())
+                .with_min_len(PARALLEL_MIN_LEN)
+                .for_each(|((p, v), a)| step(p, v, *a));
+        }
+        #[cfg(not(feature = "parallel"))]
+        for ((p, v), a) in self
+            .position
+            .iter_mut()
+            .zip(self.velocity.iter_mut())
+            .zip(self.acceleration.iter())
+        {
+            step(p, v, *a);
+        }
+        for a in self.acceleration.iter_mut() {
+            *a = vec2(0.0, 0.0);
+        }
+        for life_span in self.life_span.iter_mut() {
+            *life_span -= AGING_PER_TICK * h;
+        }
+        let mut i = 0;
+        let mut dead = 0;
+        while i < self.len() {
+            if self.life_span[i] < 0.0 {
+                self.swap_remove(i);
+                dead += 1;
+            } else {
+                i += 1;
+            }
+        }
+        dead
+    }
+
+    // Passes every particle's position and velocity to `f`, which may change them,
+    // and drops those it returns false for. Returns how many were dropped.
+    pub fn retain_mut(&mut self, mut f: impl FnMut(&mut Vec2, &mut Vec2) -> bool) -> usize {
+        let mut i = 0;
+        let mut dropped = 0;
+        while i < self.len() {
+            if f(&mut self.position[i], &mut self.velocity[i]) {
+                i += 1;
+            } else {
+                self.swap_remove(i);
+                dropped += 1;
+            }
+        }
+        dropped
+    }
+
+    fn swap_remove(&mut self, i: usize) {
+        self.position.swap_remove(i);
+        self.velocity.swap_remove(i);
+        self.acceleration.swap_remove(i);
+        self.radius.swap_remove(i);
+        self.stroke_weight.swap_remove(i);
+        self.life_span.swap_remove(i);
+        self.init_life_span.swap_remove(i);
+        self.color.swap_remove(i);
+    }
+
+    pub fn display_faded(&self, draw: &Draw, opacity: f32) {
+        for p in self.iter() {
+            p.display_faded(draw, opacity);
+        }
+    }
+}
+
+impl From<Vec<Particle>> for ParticlePool {
+    fn from(particles: Vec<Particle>) -> Self {
+        let mut pool = ParticlePool::new();
+        for p in particles {
+            pool.push(p);
+        }
+        pool
+    }
+}
+
+impl From<ParticlePool> for Vec<Particle> {
+    fn from(pool: ParticlePool) -> Self {
+        pool.iter().collect()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    fn none(_: Vec2, _: Vec2) -> Vec2 {
+        vec2(0.0, 0.0)
+    }
+
     #[test]
     fn test_new() {
         let p = Particle::new(
@@ -121,7 +320,7 @@ mod tests {
             2.0,
             255.0,
         );
-        p.update(None);
+        p.update(1.0, Integrator::default(), none);
         assert_eq!(p.position, pt2(1.0, 1.0));
     }
 
@@ -136,8 +335,11 @@ mod tests {
             255.0,
         );
         p.apply_force(vec2(1., 1.));
-        p.update(None);
-        assert_eq!(p.acceleration, vec2(1., 1.));
+        p.update(1.0, Integrator::default(), none);
+        assert_eq!(p.velocity, vec2(2., 2.));
+        // Forces last one update.
+        assert_eq!(p.acceleration, vec2(0., 0.));
+        p.update(1.0, Integrator::default(), none);
         assert_eq!(p.velocity, vec2(2., 2.));
     }
 
@@ -151,8 +353,90 @@ mod tests {
             2.0,
             255.0,
         );
-        p.update(Some(vec2(1., 1.)));
+        p.update(1.0, Integrator::default(), |_, _| vec2(1., 1.));
         assert_eq!(p.acceleration, vec2(0., 0.));
         assert_eq!(p.velocity, vec2(2., 2.));
     }
+
+    #[test]
+    fn test_aging_follows_time() {
+        let mut a = Particle::new(
+            pt2(0., 0.),
+            vec2(1., 0.),
+            hsla(0.5, 0.5, 0.5, 1.),
+            4.0,
+            2.0,
+            100.0,
+        );
+        let mut b = Particle::new(
+            pt2(0., 0.),
+            vec2(1., 0.),
+            hsla(0.5, 0.5, 0.5, 1.),
+            4.0,
+            2.0,
+            100.0,
+        );
+        a.update(1.0, Integrator::default(), none);
+        b.update(0.5, Integrator::default(), none);
+        b.update(0.5, Integrator::default(), none);
+        assert_eq!(a.life_span, 98.0);
+        assert_eq!(b.life_span, 98.0);
+        assert_eq!(a.position, b.position);
+    }
+
+    #[test]
+    fn test_pool_matches_particles() {
+        let mut pool = ParticlePool::new();
+        let mut particles = Vec::new();
+        for i in 0..5 {
+            let p = Particle::new(
+                pt2(i as f32, 0.),
+                vec2(1., 0.5),
+                hsla(0.5, 0.5, 0.5, 1.),
+                4.0,
+                2.0,
+                i as f32 * 2.0,
+            );
+            pool.push(p);
+            particles.push(p);
+        }
+        pool.apply_force(vec2(0., -0.1));
+        let field = |p: Vec2, v: Vec2| vec2(-p.y, p.x) * 0.01 - v * 0.1;
+        pool.update(1.0, Integrator::Rk4, field);
+        for p in particles.iter_mut() {
+            p.apply_force(vec2(0., -0.1));
+            p.update(1.0, Integrator::Rk4, field);
+        }
+        // The first particle dies, and the last takes its place.
+        assert_eq!(pool.len(), 4);
+        assert_eq!(pool.get(0), particles[4]);
+        assert_eq!(pool.iter().skip(1).collect::<Vec<_>>(), particles[1..4]);
+    }
+
+    #[test]
+    fn test_large_pool_matches_particles() {
+        // Enough particles to be split across threads with the `parallel` feature.
+        let mut pool = ParticlePool::new();
+        let mut particles = Vec::new();
+        for i in 0..10_000 {
+            let p = Particle::new(
+                pt2((i % 100) as f32, (i / 100) as f32),
+                vec2(1., 0.5),
+                hsla(0.5, 0.5, 0.5, 1.),
+                4.0,
+                2.0,
+                1000.0,
+            );
+            pool.push(p);
+            particles.push(p);
+        }
+        let field = |p: Vec2, _: Vec2| vec2((p.y * 0.1).sin(), (p.x * 0.1).cos()) * 0.1;
+        for _ in 0..10 {
+            pool.update(0.5, Integrator::VelocityVerlet, field);
+            for p in particles.iter_mut() {
+                p.update(0.5, Integrator::VelocityVerlet, field);
+            }
+        }
+        assert_eq!(pool.iter().collect::<Vec<_>>(), particles);
+    }
 }
@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+
+/// A small seedable random number generator (PCG-XSH-RR, 64-bit state). The
+/// emitters and their parts draw from their own `Rng`s rather than nannou's
+/// global `random_f32`, so the same config and seed reproduce the same image.
+///
+/// The algorithm is fixed here rather than borrowed from `rand`, whose
+/// generators may change output between releases.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Rng {
+    state: u64,
+    increment: u64,
+}
+
+const MULTIPLIER: u64 = 6364136223846793005;
+
+impl Rng {
+    /// A generator for `seed`. Different `stream`s with the same seed give
+    /// independent sequences.
+    pub fn new(seed: u64, stream: u64) -> Self {
+        let mut rng = Rng {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    /// A generator for `seed` whose stream is picked by `name`, e.g. an
+    /// emitter's name, so that each named thing gets its own sequence.
+    pub fn named(seed: u64, name: &str) -> Self {
+        Rng::new(seed, hash(name))
+    }
+
+    /// A new generator seeded from this one, for handing to a part that draws
+    /// in its own order.
+    pub fn split(&mut self) -> Self {
+        let seed = (self.next_u32() as u64) << 32 | self.next_u32() as u64;
+        let stream = (self.next_u32() as u64) << 32 | self.next_u32() as u64;
+        Rng::new(seed, stream)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(MULTIPLIER).wrapping_add(self.increment);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    /// A float in `[0, 1)`, as `random_f32` gives.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    /// A float in `[low, high)`.
+    pub fn range(&mut self, low: f32, high: f32) -> f32 {
+        low + self.next_f32() * (high - low)
+    }
+}
+
+/// FNV-1a, which unlike `std`'s hasher is the same on every platform and release.
+fn hash(name: &str) -> u64 {
+    name.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reference_sequence() {
+        // The first outputs of the reference PCG32 implementation for these
+        // arguments (pcg32_srandom_r(&rng, 42, 54)).
+        let mut rng = Rng::new(42, 54);
+        let values: Vec<u32> = (0..6).map(|_| rng.next_u32()).collect();
+        assert_eq!(
+            values,
+            vec![0xa15c02b7, 0x7b47f409, 0xba1d3330, 0x83d2f293, 0xbfa4784b, 0xcbed606e]
+        );
+    }
+
+    #[test]
+    fn test_streams() {
+        let draw = |mut rng: Rng| -> Vec<f32> { (0..100).map(|_| rng.next_f32()).collect() };
+        let a = draw(Rng::named(7, "a"));
+        assert_eq!(a, draw(Rng::named(7, "a")));
+        assert_ne!(a, draw(Rng::named(7, "b")));
+        assert_ne!(a, draw(Rng::named(8, "a")));
+        assert!(a.iter().all(|x| (0.0..1.0).contains(x)));
+    }
+}
@@ -0,0 +1,197 @@
+use super::config::{Config, ForcesConfig, SceneConfig};
+
+use log::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scene {
+    pub name: String,
+    pub emitters: Vec<String>,
+    /// Steps before the sequence moves on by itself, if it does.
+    pub duration: Option<u64>,
+    pub forces: Option<ForcesConfig>,
+}
+
+/// Steps through a config's scenes, either when a scene's duration runs out or
+/// when asked to with `next` and `previous`.
+#[derive(Debug, Clone)]
+pub struct Sequence {
+    scenes: Vec<Scene>,
+    pub fade_steps: u64,
+    repeat: bool,
+    current: usize,
+    started: u64,
+}
+
+/// Where a `Sequence` is: its scene index and the step that scene started at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SequenceState {
+    pub current: usize,
+    pub started: u64,
+}
+
+impl Sequence {
+    /// The config's sequence, starting at `frame`, or `None` when it has no scenes.
+    /// `config` must have been through `with_defaults`; names that aren't scenes
+    /// are skipped.
+    pub fn from_config(config: &Config, frame: u64) -> Option<Self> {
+        let sequence = config.sequence.as_ref()?;
+        let scene_configs = config.scenes.as_ref()?;
+        let scenes: Vec<Scene> = sequence
+            .scenes
+            .as_ref()
+            .unwrap()
+            .iter()
+            .filter_map(|name| match scene_configs.get(name) {
+                Some(scene) => Some(Scene::from_config(name, scene)),
+                None => {
+                    warn!("No scene named {:?}; skipping", name);
+                    None
+                }
+            })
+            .collect();
+        if scenes.is_empty() {
+            return None;
+        }
+        Some(Sequence {
+            scenes,
+            fade_steps: sequence.fade_steps.unwrap(),
+            repeat: sequence.repeat.unwrap(),
+            current: 0,
+            started: frame,
+        })
+    }
+
+    pub fn state(&self) -> SequenceState {
+        SequenceState {
+            current: self.current,
+            started: self.started,
+        }
+    }
+
+    pub fn restore(&mut self, state: SequenceState) {
+        self.current = state.current.min(self.scenes.len() - 1);
+        self.started = state.started;
+    }
+
+    pub fn current(&self) -> &Scene {
+        &self.scenes[self.current]
+    }
+
+    /// Every emitter used by any scene, in order of first appearance.
+    pub fn emitter_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for name in self.scenes.iter().flat_map(|s| s.emitters.iter()) {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        names
+    }
+
+    /// Moves on once the current scene's duration has passed. Returns whether
+    /// the scene changed.
+    pub fn update(&mut self, frame: u64) -> bool {
+        match self.current().duration {
+            Some(duration) if frame.saturating_sub(self.started) >= duration => self.next(frame),
+            _ => false,
+        }
+    }
+
+    /// Switches to the following scene, returning whether there was one.
+    pub fn next(&mut self, frame: u64) -> bool {
+        if self.current + 1 < self.scenes.len() {
+            self.go_to(self.current + 1, frame)
+        } else if self.repeat {
+            self.go_to(0, frame)
+        } else {
+            false
+        }
+    }
+
+    /// Switches to the preceding scene, returning whether there was one.
+    pub fn previous(&mut self, frame: u64) -> bool {
+        if self.current > 0 {
+            self.go_to(self.current - 1, frame)
+        } else if self.repeat {
+            self.go_to(self.scenes.len() - 1, frame)
+        } else {
+            false
+        }
+    }
+
+    fn go_to(&mut self, index: usize, frame: u64) -> bool {
+        self.started = frame;
+        if index == self.current {
+            return false;
+        }
+        self.current = index;
+        info!("Scene: {}", self.current().name);
+        true
+    }
+}
+
+impl Scene {
+    pub fn from_config(name: &str, config: &SceneConfig) -> Self {
+        Scene {
+            name: name.to_string(),
+            emitters: config.emitters.clone(),
+            duration: config.duration,
+            forces: config.forces.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_config;
+
+    fn sequence(text: &str) -> Sequence {
+        Sequence::from_config(&parse_config(text).unwrap().with_defaults(), 0).unwrap()
+    }
+
+    const TEXT: &str = r#"
+[emitters.a]
+[emitters.b]
+[emitters.c]
+[scenes.intro]
+emitters = ["a", "b"]
+duration = 100
+[scenes.outro]
+emitters = ["b", "c"]
+"#;
+
+    #[test]
+    fn test_timed_and_manual_steps() {
+        let mut sequence = sequence(TEXT);
+        assert_eq!(sequence.emitter_names(), vec!["a", "b", "c"]);
+        assert_eq!(sequence.fade_steps, 60);
+        let old_name = format!("{}[sequence]\nfade_frames = 5\n", TEXT);
+        assert_eq!(self::sequence(&old_name).fade_steps, 5);
+        assert!(!sequence.update(99));
+        assert!(sequence.update(100));
+        assert_eq!(sequence.current().name, "outro");
+        // Without a duration only a keypress moves on.
+        assert!(!sequence.update(10_000));
+        assert!(sequence.next(10_000));
+        assert_eq!(sequence.current().name, "intro");
+        assert!(!sequence.update(10_099));
+        assert!(sequence.previous(10_050));
+        assert_eq!(sequence.current().name, "outro");
+    }
+
+    #[test]
+    fn test_explicit_order_without_repeat() {
+        let mut sequence = sequence(&format!(
+            "{}[sequence]\nscenes = [\"outro\", \"intro\"]\nrepeat = false\n",
+            TEXT
+        ));
+        assert_eq!(sequence.current().name, "outro");
+        assert!(!sequence.previous(0));
+        assert!(sequence.next(0));
+        assert!(!sequence.update(100));
+        assert!(!sequence.next(100));
+        assert_eq!(sequence.current().name, "intro");
+    }
+}
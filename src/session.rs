@@ -0,0 +1,330 @@
+use super::config::Config;
+use super::snapshot::Snapshot;
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Something done to a running sketch from outside: a key press or a config
+/// reload. Logged with the frame it happened before, so that a replay can do
+/// the same thing at the same point.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// Where the session began, whether from a config file or a snapshot.
+    /// Always the first event of a log.
+    Start {
+        snapshot: Box<Snapshot>,
+    },
+    TogglePause,
+    NextScene,
+    PreviousScene,
+    /// A frame captured to an image.
+    Capture,
+    /// A snapshot saved to disk.
+    SaveSnapshot,
+    /// The config file was edited, leaving this effective config.
+    Load {
+        config: Box<Config>,
+        keep_particles: bool,
+        keep_canvas: bool,
+    },
+    /// A rendered frame, which took `steps` steps of `dt`. Logged after the
+    /// events that came before it, so a replay draws the same images.
+    Frame {
+        steps: u32,
+    },
+    /// Where the session stopped. Always the last event of a complete log, so
+    /// a replay runs to the same frame rather than stopping at the last key
+    /// press.
+    End,
+}
+
+/// One line of a session log.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Entry {
+    pub frame: u64,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// Writes a session log, one JSON entry per line. Each entry is flushed as it
+/// is written, so the log survives the sketch being closed or crashing.
+#[derive(Debug)]
+pub struct Recorder<W: Write = BufWriter<File>> {
+    writer: W,
+}
+
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        if let Some(parent) = path.as_ref().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(Recorder::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(writer: W) -> Self {
+        Recorder { writer }
+    }
+
+    pub fn record(&mut self, frame: u64, event: &Event) -> io::Result<()> {
+        let entry = Entry {
+            frame,
+            event: event.clone(),
+        };
+        serde_json::to_writer(&mut self.writer, &entry)?;
+        writeln!(self.writer)?;
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// A session log read back, handing out its events as the replay reaches
+/// their frames.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    start: Snapshot,
+    entries: VecDeque<Entry>,
+}
+
+impl Replay {
+    pub fn from_jsonl(text: &str) -> io::Result<Self> {
+        let mut entries = VecDeque::new();
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: Entry = serde_json::from_str(line).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", i + 1, e))
+            })?;
+            entries.push_back(entry);
+        }
+        let start = match entries.pop_front() {
+            Some(Entry {
+                event: Event::Start { snapshot },
+                ..
+            }) => *snapshot,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "session log does not begin with a start event",
+                ))
+            }
+        };
+        start.check_version()?;
+        Ok(Replay { start, entries })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Replay::from_jsonl(&fs::read_to_string(path)?)
+    }
+
+    /// The snapshot the session began from.
+    pub fn start(&self) -> &Snapshot {
+        &self.start
+    }
+
+    /// Takes the events of the next rendered frame, in order: everything up
+    /// to and including its frame event, or the rest of the log if there are
+    /// no frame events left.
+    pub fn next_frame(&mut self) -> Vec<Event> {
+        let mut events = Vec::new();
+        while let Some(entry) = self.entries.pop_front() {
+            let done = matches!(entry.event, Event::Frame { .. });
+            events.push(entry.event);
+            if done {
+                break;
+            }
+        }
+        events
+    }
+
+    /// The frame the session ended at, if the log got as far as recording it.
+    pub fn end(&self) -> Option<u64> {
+        match self.entries.back() {
+            Some(Entry {
+                frame,
+                event: Event::End,
+            }) => Some(*frame),
+            _ => None,
+        }
+    }
+
+    /// Whether every event has been handed out.
+    pub fn is_finished(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{parse_config, Animated};
+    use crate::world::{test_world, World, WorldState};
+
+    const TEXT: &str = r#"
+seed = 5
+[emitters.a]
+randomize_position = true
+[emitters.b]
+noise_field = true
+noise_scale = 0.01
+[emitters.b.emission]
+mode = "poisson"
+rate = 40
+[scenes.one]
+emitters = ["a"]
+[scenes.two]
+emitters = ["a", "b"]
+"#;
+
+    /// Runs a session live, logging it, for `frames` steps. Each of `events`
+    /// happens at the first frame boundary at or after its frame. Also returns
+    /// the step count after each rendered frame.
+    fn run_live(events: &[(u64, Event)], frames: u64) -> (World, String, Vec<u64>) {
+        let mut world = test_world(TEXT);
+        let mut recorder = Recorder::new(Vec::new());
+        let start = Event::Start {
+            snapshot: Box::new(world.snapshot()),
+        };
+        recorder.record(0, &start).unwrap();
+        let mut events = events.iter().peekable();
+        let mut drawn = Vec::new();
+        while world.frame() < frames {
+            while let Some((_, event)) = events.next_if(|(f, _)| *f <= world.frame()) {
+                recorder.record(world.frame(), event).unwrap();
+                world.apply(event);
+            }
+            // Uneven frame times, as a live window would have: some frames
+            // take no step and some take several.
+            let frame = world.frame();
+            let elapsed = [0.5, 2.0, 1.0, 3.5][drawn.len() % 4];
+            let steps = world.advance(world.dt() * elapsed);
+            recorder.record(frame, &Event::Frame { steps }).unwrap();
+            drawn.push(world.frame());
+        }
+        recorder.record(world.frame(), &Event::End).unwrap();
+        let log = String::from_utf8(recorder.into_inner()).unwrap();
+        (world, log, drawn)
+    }
+
+    /// Replays take logged steps rather than timing frames, so only the live
+    /// world holds a leftover part of a step.
+    fn assert_same_state(world: &World, live: &World) {
+        let live = WorldState {
+            accumulator: 0.0,
+            ..live.state()
+        };
+        assert_eq!(world.state(), live);
+    }
+
+    /// Lines of `log` other than frame events.
+    fn count_events(log: &str) -> usize {
+        log.lines()
+            .filter(|l| !l.contains("\"event\":\"frame\""))
+            .count()
+    }
+
+    #[test]
+    fn test_replay_matches_live() {
+        let mut reloaded = parse_config(TEXT).unwrap();
+        reloaded
+            .emitters
+            .as_mut()
+            .unwrap()
+            .get_mut("a")
+            .unwrap()
+            .radius = Some(Animated::Constant(9.0));
+        let events = vec![
+            (12, Event::NextScene),
+            (30, Event::TogglePause),
+            (30, Event::Capture),
+            (45, Event::TogglePause),
+            (
+                60,
+                Event::Load {
+                    config: Box::new(reloaded.with_defaults()),
+                    keep_particles: true,
+                    keep_canvas: false,
+                },
+            ),
+            (75, Event::PreviousScene),
+        ];
+        let (live, log, drawn) = run_live(&events, 120);
+        assert_eq!(count_events(&log), 2 + events.len());
+
+        let (world, applied, replayed) = replay(&log);
+        assert_eq!(applied, events.len() + 1);
+        assert_eq!(replayed, drawn);
+        assert!(live.particles().count() > 0);
+        assert_eq!(world.frame(), live.frame());
+        assert_same_state(&world, &live);
+    }
+
+    #[test]
+    fn test_replay_without_events() {
+        let (live, log, drawn) = run_live(&[], 90);
+        assert_eq!(count_events(&log), 2);
+        let (world, applied, replayed) = replay(&log);
+        assert_eq!(applied, 1);
+        assert_eq!(replayed, drawn);
+        assert!(live.frame() >= 90);
+        assert_same_state(&world, &live);
+    }
+
+    #[test]
+    fn test_replay_multi_step_frames() {
+        let (live, log, drawn) = run_live(&[], 30);
+        let steps: Vec<u64> = drawn
+            .iter()
+            .scan(0, |last, &frame| {
+                Some(frame - std::mem::replace(last, frame))
+            })
+            .collect();
+        assert!(
+            steps.contains(&0) && steps.iter().any(|&s| s > 1),
+            "{:?}",
+            steps
+        );
+        let (world, _, replayed) = replay(&log);
+        assert_eq!(replayed, drawn);
+        assert_same_state(&world, &live);
+    }
+
+    /// Replays `log` as the sketch does, a rendered frame at a time until the
+    /// end event. Returns the world, how many events other than frames were
+    /// applied and the step count after each rendered frame.
+    fn replay(log: &str) -> (World, usize, Vec<u64>) {
+        let mut replay = Replay::from_jsonl(log).unwrap();
+        let end = replay.end().unwrap();
+        let mut world = World::from_snapshot(replay.start().clone(), test_world(TEXT).bounds());
+        let mut applied = 0;
+        let mut drawn = Vec::new();
+        while !replay.is_finished() {
+            for event in replay.next_frame() {
+                world.apply(&event);
+                match event {
+                    Event::Frame { .. } => drawn.push(world.frame()),
+                    _ => applied += 1,
+                }
+            }
+        }
+        assert_eq!(world.frame(), end);
+        (world, applied, drawn)
+    }
+
+    #[test]
+    fn test_missing_start() {
+        let error = Replay::from_jsonl("{\"frame\":3,\"event\":\"next_scene\"}\n").unwrap_err();
+        assert!(error.to_string().contains("start"), "{}", error);
+        let error = Replay::from_jsonl("{\"frame\":3}\n").unwrap_err();
+        assert!(error.to_string().contains("line 1"), "{}", error);
+    }
+}
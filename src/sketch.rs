@@ -2,7 +2,7 @@ use nannou::{geom::rect, prelude::*};
 use log::*;
 use particle_lib::mover::{epicycloid, p_elipse};
 
-use particle_lib::config::MoverConfig;
+use particle_lib::config::{Animated, MoverConfig};
 use particle_lib::mover::Mover;
 
 
@@ -26,8 +26,8 @@ fn view(app: &App, frame: Frame) {
         outer,
         scale: w_h,
         translation: Some(translate),
-        rotation_angle: Some(0.),
-        rotation_speed: Some(0.),
+        rotation_angle: Some(Animated::Constant(0.)),
+        rotation_speed: Some(Animated::Constant(0.)),
     };  
     let mover = Mover::from_config("test_mover".to_string(), config);    
     let position = mover.get_postion(index);
@@ -0,0 +1,212 @@
+use super::config::Config;
+use super::world::WorldState;
+
+use nannou::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Bumped when `Snapshot` changes in a way older files can't be read as.
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+/// A run frozen at one step: the config it was running, with defaults filled
+/// in, and everything that has changed since it started. Animated values are
+/// functions of the clock, so they come back with it.
+///
+/// Snapshots are JSON rather than TOML, since random number generator states
+/// need the full range of `u64`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub config: Config,
+    pub world: WorldState,
+}
+
+impl Snapshot {
+    pub fn new(config: Config, world: WorldState) -> Self {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            config,
+            world,
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+
+    pub fn from_json(text: &str) -> io::Result<Self> {
+        let snapshot: Snapshot = serde_json::from_str(text)?;
+        snapshot.check_version()?;
+        Ok(snapshot)
+    }
+
+    /// Fails for snapshots written by a newer version of the sketch.
+    pub fn check_version(&self) -> io::Result<()> {
+        if self.version > SNAPSHOT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "snapshot version {} is newer than the supported version {}",
+                    self.version, SNAPSHOT_VERSION
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        if let Some(parent) = path.as_ref().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_json()?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Snapshot::from_json(&fs::read_to_string(path)?)
+    }
+}
+
+/// A serialisable `Hsla`, with the hue in degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Color {
+    pub hue: f32,
+    pub saturation: f32,
+    pub lightness: f32,
+    pub alpha: f32,
+}
+
+impl From<Hsla> for Color {
+    fn from(color: Hsla) -> Self {
+        Color {
+            hue: color.hue.to_raw_degrees(),
+            saturation: color.saturation,
+            lightness: color.lightness,
+            alpha: color.alpha,
+        }
+    }
+}
+
+impl From<Color> for Hsla {
+    fn from(color: Color) -> Self {
+        Hsla::new(color.hue, color.saturation, color.lightness, color.alpha)
+    }
+}
+
+/// For `#[serde(with = "snapshot::hsla")]` on `Hsla` fields.
+pub mod hsla {
+    use super::Color;
+    use nannou::prelude::Hsla;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &Hsla, serializer: S) -> Result<S::Ok, S::Error> {
+        Color::from(*color).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Hsla, D::Error> {
+        Color::deserialize(deserializer).map(Hsla::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{test_world, World};
+
+    const TEXT: &str = r#"
+seed = 3
+[emitters.a]
+color_picker = "range"
+randomize_position = true
+mover = "circle"
+[emitters.b]
+noise_field = true
+noise_scale = 0.01
+noise_strength = "0.2 + 0.1*sin(t)"
+[emitters.b.emission]
+mode = "poisson"
+rate = 40
+[color_pickers.range]
+num_colors = 7
+range_hue = [0, 360]
+[movers.circle]
+mover_type = "p_elipse"
+inner = [1, 1]
+outer = [1, 1]
+scale = [200, 200]
+rotation_speed = "0.5 + 0.5 * sin(t)"
+[attractors.pull]
+mover = "circle"
+strength = 0.01
+[scenes.one]
+emitters = ["a", "b"]
+duration = 40
+[scenes.two]
+emitters = ["b"]
+"#;
+
+    fn particles(world: &World) -> Vec<String> {
+        world.particles().map(|p| format!("{:?}", p)).collect()
+    }
+
+    #[test]
+    fn test_resume_exactly() {
+        let mut world = test_world(TEXT);
+        for _ in 0..30 {
+            world.step();
+        }
+        let json = world.snapshot().to_json().unwrap();
+        let snapshot = Snapshot::from_json(&json).unwrap();
+        assert_eq!(snapshot, world.snapshot());
+
+        let mut resumed = World::from_snapshot(snapshot, world.bounds());
+        assert_eq!(resumed.state(), world.state());
+        // Past the scene change at frame 40, mid fade.
+        for _ in 0..50 {
+            world.step();
+            resumed.step();
+        }
+        assert_eq!(resumed.frame(), 80);
+        assert!(world.particles().count() > 0);
+        assert_eq!(particles(&resumed), particles(&world));
+        assert_eq!(resumed.state(), world.state());
+    }
+
+    #[test]
+    fn test_movers_resume_in_phase() {
+        let text = r#"
+selected_emitters = ["a"]
+[emitters.a]
+mover = "spinner"
+[movers.spinner]
+mover_type = "p_elipse"
+inner = [1, 2]
+outer = [1, 1]
+scale = [300, 200]
+rotation_speed = "2 + sin(3 * t)"
+"#;
+        let positions = |world: &World| -> Vec<Point2> {
+            world.emitters().iter().map(|e| e.position()).collect()
+        };
+        let mut world = test_world(text);
+        for _ in 0..200 {
+            world.step();
+        }
+        let mut resumed = World::from_snapshot(world.snapshot(), world.bounds());
+        assert_eq!(positions(&resumed).len(), 1);
+        for _ in 0..20 {
+            world.step();
+            resumed.step();
+            assert_eq!(positions(&resumed), positions(&world));
+        }
+    }
+
+    #[test]
+    fn test_newer_version() {
+        let mut snapshot = test_world(TEXT).snapshot();
+        snapshot.version = SNAPSHOT_VERSION + 1;
+        let error = Snapshot::from_json(&snapshot.to_json().unwrap()).unwrap_err();
+        assert!(error.to_string().contains("newer"), "{}", error);
+    }
+}
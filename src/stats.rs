@@ -0,0 +1,281 @@
+use super::emitter::Emitter;
+
+use nannou::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Measurements of one emitter after a step.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct EmitterStats {
+    pub name: String,
+    /// Particles alive.
+    pub live: usize,
+    /// Particles released during the step.
+    pub births: usize,
+    /// Particles that died during the step.
+    pub deaths: usize,
+    /// Particle speeds, in pixels per tick.
+    pub mean_speed: f32,
+    pub max_speed: f32,
+    /// The smallest box holding every live particle, as `[left, bottom, right, top]`.
+    pub bounding_box: Option<[f32; 4]>,
+    /// Where the emitter is, following its mover if it has one.
+    pub position: Vec2,
+}
+
+impl EmitterStats {
+    pub fn of(emitter: &Emitter) -> Self {
+        let particles = &emitter.particles;
+        let speeds = particles.velocities().iter().map(|v| v.length());
+        let (total_speed, max_speed) = speeds.fold((0.0, 0.0), |(sum, max): (f32, f32), s| {
+            (sum + s, max.max(s))
+        });
+        let bounding_box = particles.positions().iter().fold(None, |bounds, p| {
+            let [left, bottom, right, top] = bounds.unwrap_or([p.x, p.y, p.x, p.y]);
+            Some([left.min(p.x), bottom.min(p.y), right.max(p.x), top.max(p.y)])
+        });
+        EmitterStats {
+            name: emitter.name().to_string(),
+            live: particles.len(),
+            births: emitter.births(),
+            deaths: emitter.deaths(),
+            mean_speed: if particles.is_empty() {
+                0.0
+            } else {
+                total_speed / particles.len() as f32
+            },
+            max_speed,
+            bounding_box,
+            position: emitter.position(),
+        }
+    }
+}
+
+/// Measurements of every emitter after a step.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct FrameStats {
+    /// Steps taken, counting this one.
+    pub frame: u64,
+    /// Simulated seconds.
+    pub time: f64,
+    pub emitters: Vec<EmitterStats>,
+}
+
+impl FrameStats {
+    /// Particles alive across every emitter.
+    pub fn live(&self) -> usize {
+        self.emitters.iter().map(|e| e.live).sum()
+    }
+
+    /// A few lines for drawing over the sketch.
+    pub fn summary(&self) -> String {
+        let mut text = format!(
+            "frame {}  {:.2}s  {} particles\n",
+            self.frame,
+            self.time,
+            self.live()
+        );
+        for e in self.emitters.iter() {
+            let _ = writeln!(
+                text,
+                "{}: {} live  +{} -{}  speed {:.2} / {:.2}",
+                e.name, e.live, e.births, e.deaths, e.mean_speed, e.max_speed
+            );
+        }
+        text
+    }
+}
+
+const CSV_HEADER: &str = "frame,time,emitter,live,births,deaths,mean_speed,max_speed,\
+left,bottom,right,top,x,y";
+
+/// How a `StatsWriter` lays out its file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsFormat {
+    /// One row per emitter per step.
+    Csv,
+    /// One `FrameStats` per line, as JSON.
+    Jsonl,
+}
+
+impl StatsFormat {
+    /// CSV for a `.csv` file and JSON lines for anything else.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("csv") => StatsFormat::Csv,
+            _ => StatsFormat::Jsonl,
+        }
+    }
+}
+
+/// Writes `FrameStats` to a file as they come.
+#[derive(Debug)]
+pub struct StatsWriter<W: Write = BufWriter<File>> {
+    writer: W,
+    format: StatsFormat,
+}
+
+impl StatsWriter {
+    /// A writer for `path` in the format its extension implies.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        if let Some(parent) = path.as_ref().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let format = StatsFormat::from_path(&path);
+        StatsWriter::new(BufWriter::new(File::create(path)?), format)
+    }
+}
+
+impl<W: Write> StatsWriter<W> {
+    pub fn new(mut writer: W, format: StatsFormat) -> io::Result<Self> {
+        if format == StatsFormat::Csv {
+            writeln!(writer, "{}", CSV_HEADER)?;
+        }
+        Ok(StatsWriter { writer, format })
+    }
+
+    pub fn write(&mut self, stats: &FrameStats) -> io::Result<()> {
+        match self.format {
+            StatsFormat::Csv => {
+                for e in stats.emitters.iter() {
+                    let bounding_box = match e.bounding_box {
+                        Some([left, bottom, right, top]) => {
+                            format!("{},{},{},{}", left, bottom, right, top)
+                        }
+                        None => ",,,".to_string(),
+                    };
+                    writeln!(
+                        self.writer,
+                        "{},{},{},{},{},{},{},{},{},{},{}",
+                        stats.frame,
+                        stats.time,
+                        csv_field(&e.name),
+                        e.live,
+                        e.births,
+                        e.deaths,
+                        e.mean_speed,
+                        e.max_speed,
+                        bounding_box,
+                        e.position.x,
+                        e.position.y
+                    )?;
+                }
+            }
+            StatsFormat::Jsonl => {
+                serde_json::to_writer(&mut self.writer, stats)?;
+                writeln!(self.writer)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Quotes `field` if it would otherwise break the row.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{test_world, World};
+
+    const TEXT: &str = r#"
+selected_emitters = ["a", "b"]
+[emitters.a]
+life_span = 20
+[emitters.a.emission]
+mode = "steady"
+rate = 60
+[emitters.b]
+mover = "circle"
+[emitters.b.emission]
+start = 100
+[movers.circle]
+mover_type = "p_elipse"
+inner = [1, 1]
+outer = [1, 1]
+scale = [100, 100]
+rotation_speed = 0.5
+"#;
+
+    fn world() -> World {
+        let mut world = test_world(TEXT);
+        world.collect_stats(true);
+        world
+    }
+
+    #[test]
+    fn test_births_and_deaths() {
+        let mut world = world();
+        for _ in 0..30 {
+            world.step();
+        }
+        let stats = world.take_stats();
+        assert_eq!(stats.len(), 30);
+        assert_eq!(stats[29].frame, 30);
+        // One particle a step. A life span of 20 lasts ten updates and the
+        // particle is dropped on the eleventh, once below zero.
+        let a: Vec<&EmitterStats> = stats.iter().map(|s| &s.emitters[0]).collect();
+        assert!(a.iter().all(|e| e.name == "a" && e.births == 1));
+        assert_eq!(a[5].live, 6);
+        assert_eq!(a[5].deaths, 0);
+        assert_eq!(a[29].live, 11);
+        assert_eq!(a[29].deaths, 1);
+        assert!(a[29].max_speed >= a[29].mean_speed && a[29].mean_speed > 0.0);
+        let [left, bottom, right, top] = a[29].bounding_box.unwrap();
+        assert!(left < right && bottom < top);
+
+        let b = &stats[29].emitters[1];
+        assert_eq!((b.live, b.bounding_box), (0, None));
+        assert_ne!(b.position, stats[0].emitters[1].position);
+        assert!(world.take_stats().is_empty());
+    }
+
+    #[test]
+    fn test_write_csv_and_jsonl() {
+        let mut world = world();
+        for _ in 0..3 {
+            world.step();
+        }
+        let stats = world.take_stats();
+
+        let mut csv = StatsWriter::new(Vec::new(), StatsFormat::Csv).unwrap();
+        for s in stats.iter() {
+            csv.write(s).unwrap();
+        }
+        let csv = String::from_utf8(csv.into_inner()).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 1 + 3 * 2);
+        assert_eq!(lines[0], CSV_HEADER);
+        assert!(lines.iter().all(|l| l.split(',').count() == 14), "{}", csv);
+        assert!(lines[6].starts_with("3,") && lines[6].contains(",b,0,0,0,0,0,,,,,"));
+
+        let mut jsonl = StatsWriter::new(Vec::new(), StatsFormat::Jsonl).unwrap();
+        for s in stats.iter() {
+            jsonl.write(s).unwrap();
+        }
+        let jsonl = String::from_utf8(jsonl.into_inner()).unwrap();
+        let read: Vec<FrameStats> = jsonl
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(read, stats);
+        assert_eq!(StatsFormat::from_path("out/stats.CSV"), StatsFormat::Csv);
+    }
+}
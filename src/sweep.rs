@@ -0,0 +1,264 @@
+use super::config::{self, Config, SweepValues};
+use super::format::Format;
+use super::validation::Report;
+
+/// Steps each variant runs for when the sweep doesn't say.
+pub const DEFAULT_FRAMES: u64 = 600;
+
+/// Significant digits `from`/`to` ranges are rounded to.
+const SIGNIFICANT_DIGITS: usize = 12;
+
+#[derive(Debug, Clone)]
+pub struct Variant {
+    /// The swept values, e.g. `emitters.rainbow.noise_scale=0.005_seed=3`.
+    pub label: String,
+    pub config: Config,
+}
+
+#[derive(Debug, Clone)]
+pub struct Sweep {
+    pub frames: u64,
+    pub variants: Vec<Variant>,
+}
+
+impl Variant {
+    /// Capture file name for the `index`th variant, safe for any file system.
+    pub fn file_name(&self, index: usize) -> String {
+        let prefix = self.config.capture_prefix.as_deref().unwrap_or("");
+        let label: String = self
+            .label
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '_' | '=' | '-' => c,
+                _ => '-',
+            })
+            .collect();
+        format!("{}{:03}_{}", prefix, index, label)
+    }
+}
+
+/// Expands the `[sweep]` section of a config into the Cartesian product of its
+/// values. Every variant is validated like a standalone config and has the
+/// `[sweep]` section removed. Swept values are set like overrides, after
+/// `overrides`, so they too reach entries inheriting them.
+pub fn expand(contents: &str, format: Format, overrides: &[String]) -> Result<Sweep, Report> {
+    let base = config::parse_config_as(contents, format, overrides)?;
+    let sweep = match base.sweep {
+        Some(sweep) => sweep,
+        None => {
+            return Ok(Sweep {
+                frames: DEFAULT_FRAMES,
+                variants: Vec::new(),
+            })
+        }
+    };
+    let axes: Vec<(String, Vec<toml::Value>)> = sweep
+        .values
+        .into_iter()
+        .map(|(path, values)| (path, values.expand()))
+        .collect();
+    let mut variants = Vec::new();
+    for combination in product(&axes) {
+        let mut variant_overrides = overrides.to_vec();
+        let mut label = Vec::new();
+        for (path, value) in combination.iter() {
+            // A toml `Value` always renders back to valid TOML.
+            variant_overrides.push(format!("{}={}", path, value));
+            label.push(format!("{}={}", path, value.to_string().trim_matches('"')));
+        }
+        let (mut table, report) = config::parse_table(contents, format, &variant_overrides)
+            .map_err(|report| sweep_paths(report, &combination))?;
+        table.remove("sweep");
+        let config = config::config_from_table(table, contents, report)?;
+        variants.push(Variant {
+            label: label.join("_"),
+            config,
+        });
+    }
+    Ok(Sweep {
+        frames: sweep.frames.unwrap_or(DEFAULT_FRAMES),
+        variants,
+    })
+}
+
+/// Points problems with a swept path at its place in `[sweep.values]`.
+fn sweep_paths(mut report: Report, combination: &[(&str, &toml::Value)]) -> Report {
+    for d in report.diagnostics.iter_mut() {
+        if combination.iter().any(|(path, _)| d.path == *path) {
+            d.path = format!("sweep.values.{}", d.path);
+        }
+    }
+    report
+}
+
+impl SweepValues {
+    fn expand(&self) -> Vec<toml::Value> {
+        match self {
+            SweepValues::List(values) => values.clone(),
+            // Validation makes sure there is at least one step.
+            SweepValues::Range { from, to, steps } => {
+                let steps = *steps;
+                (0..steps)
+                    .map(|i| {
+                        let v = if steps == 1 {
+                            *from
+                        } else {
+                            from + (to - from) * i as f64 / (steps - 1) as f64
+                        };
+                        // Round off float error, which would otherwise show in
+                        // labels and file names as `0.007000000000000001`.
+                        let v: f64 = format!("{:.*e}", SIGNIFICANT_DIGITS - 1, v)
+                            .parse()
+                            .unwrap();
+                        // Keep whole numbers integral so they also fit integer fields.
+                        if v.fract() == 0.0 {
+                            toml::Value::Integer(v as i64)
+                        } else {
+                            toml::Value::Float(v)
+                        }
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+fn product(axes: &[(String, Vec<toml::Value>)]) -> Vec<Vec<(&str, &toml::Value)>> {
+    let mut combinations = vec![Vec::new()];
+    for (path, values) in axes {
+        let mut next = Vec::new();
+        for combination in combinations.iter() {
+            for value in values {
+                let mut c: Vec<(&str, &toml::Value)> = combination.clone();
+                c.push((path.as_str(), value));
+                next.push(c);
+            }
+        }
+        combinations = next;
+    }
+    combinations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::Problem;
+
+    const TEXT: &str = r#"
+selected_emitters = ["e"]
+capture_prefix = "sweep_"
+[emitters.e]
+noise_scale = 0.005
+
+[sweep]
+frames = 10
+[sweep.values]
+"emitters.e.noise_scale" = [0.003, 0.008]
+seed = { from = 1, to = 3, steps = 3 }
+"#;
+
+    #[test]
+    fn test_expand() {
+        let sweep = expand(TEXT, Format::Toml, &[]).unwrap();
+        assert_eq!(sweep.frames, 10);
+        assert_eq!(sweep.variants.len(), 6);
+        let last = &sweep.variants[5];
+        assert_eq!(last.label, "emitters.e.noise_scale=0.008_seed=3");
+        assert_eq!(last.config.seed, Some(3));
+        assert_eq!(
+            last.config.emitters.as_ref().unwrap()["e"].noise_scale,
+            Some(config::Animated::Constant(0.008))
+        );
+        assert!(last.config.sweep.is_none());
+        assert_eq!(
+            last.file_name(5),
+            "sweep_005_emitters.e.noise_scale=0.008_seed=3"
+        );
+    }
+
+    #[test]
+    fn test_inherited_values() {
+        let text = r#"
+selected_emitters = ["child"]
+[defaults.emitter]
+radius = 4
+[emitters.base]
+noise_scale = 0.005
+[emitters.child]
+extends = "base"
+[sweep.values]
+"emitters.base.noise_scale" = [0.003]
+"defaults.emitter.radius" = [7]
+"#;
+        let sweep = expand(text, Format::Toml, &[]).unwrap();
+        let child = &sweep.variants[0].config.emitters.as_ref().unwrap()["child"];
+        assert_eq!(child.noise_scale, Some(config::Animated::Constant(0.003)));
+        assert_eq!(child.radius, Some(config::Animated::Constant(7.0)));
+    }
+
+    #[test]
+    fn test_range() {
+        let values = SweepValues::Range {
+            from: 0.0,
+            to: 0.5,
+            steps: 3,
+        };
+        assert_eq!(
+            values.expand(),
+            vec![
+                toml::Value::Integer(0),
+                toml::Value::Float(0.25),
+                toml::Value::Float(0.5)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_range_rounding_and_steps() {
+        let values = SweepValues::Range {
+            from: 0.001,
+            to: 0.01,
+            steps: 4,
+        };
+        assert_eq!(
+            values.expand(),
+            vec![
+                toml::Value::Float(0.001),
+                toml::Value::Float(0.004),
+                toml::Value::Float(0.007),
+                toml::Value::Float(0.01)
+            ]
+        );
+
+        let text = TEXT.replace("steps = 3", "steps = 0");
+        let report = expand(&text, Format::Toml, &[]).unwrap_err();
+        let paths: Vec<&str> = report.errors().map(|d| d.path.as_str()).collect();
+        assert_eq!(paths, vec!["sweep.values.seed.steps"]);
+    }
+
+    #[test]
+    fn test_bad_paths_and_values() {
+        let report = expand(
+            &TEXT.replace("emitters.e.noise_scale", "emitters.f.noise_scale"),
+            Format::Toml,
+            &[],
+        )
+        .unwrap_err();
+        assert_eq!(
+            report.diagnostics[0].problem,
+            Problem::UnknownPath("emitters.f.noise_scale".to_string())
+        );
+        assert_eq!(
+            report.diagnostics[0].path,
+            "sweep.values.emitters.f.noise_scale"
+        );
+
+        let report = expand(
+            &TEXT.replace("[0.003, 0.008]", "[0.003, \"big\"]"),
+            Format::Toml,
+            &[],
+        )
+        .unwrap_err();
+        assert!(report.has_errors());
+    }
+}
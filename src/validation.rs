@@ -0,0 +1,1063 @@
+use super::config::{
+    Animated, ColorPickerConfig, Config, EmitterConfig, ForcesConfig, MoverConfig, SweepValues,
+};
+use super::expression;
+use super::migration::CONFIG_VERSION;
+use super::mover::MOVER_TYPES;
+use super::world::{MAX_DT, MIN_DT, TICKS_PER_SECOND};
+
+use nannou::prelude::*;
+use std::fmt;
+use std::ops::Range;
+use toml_edit::{Table, TableLike};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    Parse(String),
+    UnknownEmitter(String),
+    UnknownScene(String),
+    DanglingColorPicker(String),
+    DanglingMover(String),
+    UnknownMoverType(String),
+    UnknownAttractor(String),
+    OutOfRange {
+        value: f32,
+        min: f32,
+        max: f32,
+    },
+    UnknownKey(String),
+    UnsupportedVersion(i64),
+    Migrated(String),
+    UnknownParent(String),
+    InheritanceCycle(Vec<String>),
+    UnknownPath(String),
+    Expression(String),
+    /// Set, but has no effect unless the given setting is on.
+    Ignored(String),
+}
+
+/// 1-based line and column of a diagnostic in the config source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub problem: Problem,
+    /// Dotted path of the offending key, e.g. `emitters.rainbow.color_picker`.
+    pub path: String,
+    pub location: Option<Location>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::Parse(message) => write!(f, "{}", message),
+            Problem::UnknownEmitter(name) => write!(f, "unknown emitter {:?}", name),
+            Problem::UnknownScene(name) => write!(f, "unknown scene {:?}", name),
+            Problem::DanglingColorPicker(name) => write!(f, "unknown color picker {:?}", name),
+            Problem::DanglingMover(name) => write!(f, "unknown mover {:?}", name),
+            Problem::UnknownAttractor(name) => write!(f, "unknown attractor {:?}", name),
+            Problem::UnknownMoverType(name) => write!(
+                f,
+                "unknown mover_type {:?} (expected one of {:?})",
+                name, MOVER_TYPES
+            ),
+            Problem::OutOfRange { value, min, max } => {
+                write!(f, "value {} is outside of [{}, {}]", value, min, max)
+            }
+            Problem::UnknownKey(key) => write!(f, "unknown key {:?}", key),
+            Problem::UnsupportedVersion(version) => write!(
+                f,
+                "config version {} is newer than the supported version {}",
+                version, CONFIG_VERSION
+            ),
+            Problem::Migrated(note) => write!(f, "migrated: {}", note),
+            Problem::UnknownParent(name) => write!(f, "extends unknown entry {:?}", name),
+            Problem::UnknownPath(path) => write!(f, "no config table for {:?}", path),
+            Problem::Expression(message) => write!(f, "invalid expression: {}", message),
+            Problem::Ignored(setting) => write!(f, "ignored unless {}", setting),
+            Problem::InheritanceCycle(chain) => {
+                write!(f, "inheritance cycle {}", chain.join(" -> "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.severity)?;
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "{}", self.problem)?;
+        if let Some(location) = self.location {
+            write!(f, " (line {}, column {})", location.line, location.column)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for d in self.diagnostics.iter() {
+            writeln!(f, "{}", d)?;
+        }
+        Ok(())
+    }
+}
+
+impl Report {
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Warning)
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    pub fn extend(&mut self, other: Report) {
+        self.diagnostics.extend(other.diagnostics);
+    }
+
+    /// A report holding a single error at `path` that has no place in the source.
+    pub fn error(problem: Problem, path: &str) -> Self {
+        Report {
+            diagnostics: vec![Diagnostic {
+                severity: Severity::Error,
+                problem,
+                path: path.to_string(),
+                location: None,
+            }],
+        }
+    }
+
+    /// A report holding a single syntax or type error found while parsing `source`.
+    pub fn parse_error(message: &str, span: Option<Range<usize>>, source: &str) -> Self {
+        Report {
+            diagnostics: vec![Diagnostic {
+                severity: Severity::Error,
+                problem: Problem::Parse(message.to_string()),
+                path: String::new(),
+                location: span.map(|span| location(source, span.start)),
+            }],
+        }
+    }
+}
+
+/// Checks a parsed `Config` for problems that deserialization alone does not catch.
+/// `root` is the resolved TOML table the config was deserialized from; its spans
+/// point into `source` and give each diagnostic a line and column. `unknown_keys`
+/// are the paths serde skipped; they are warnings unless the config sets `strict`.
+pub fn validate(
+    config: &Config,
+    root: &Table,
+    source: &str,
+    unknown_keys: &[Vec<String>],
+) -> Report {
+    let mut validator = Validator {
+        source,
+        root,
+        report: Report::default(),
+    };
+    validator.check_range(config.dt, MIN_DT, MAX_DT, &["dt"]);
+    validator.check_references(config);
+    validator.check_scenes(config);
+    validator.check_movers(config);
+    validator.check_color_pickers(config);
+    validator.check_expressions(config);
+    validator.check_emission(config);
+    validator.check_boundaries(config);
+    validator.check_attractors(config);
+    validator.check_all_forces(config);
+    validator.check_noise(config);
+    validator.check_vortices(config);
+    validator.check_sweep(config);
+    validator.check_unknown_keys(unknown_keys, config.strict.unwrap_or(false));
+    validator.report
+}
+
+struct Validator<'a> {
+    source: &'a str,
+    root: &'a Table,
+    report: Report,
+}
+
+impl<'a> Validator<'a> {
+    fn push(&mut self, severity: Severity, problem: Problem, path: &[&str]) {
+        let span = self.span(path, false);
+        self.report.diagnostics.push(Diagnostic {
+            severity,
+            problem,
+            path: path.join("."),
+            location: span.map(|span| location(self.source, span.start)),
+        });
+    }
+
+    /// Byte range of the value at `path`, or of its key when `key` is set.
+    /// A numeric segment after an array indexes into it.
+    fn span(&self, path: &[&str], key: bool) -> Option<Range<usize>> {
+        let mut table: &dyn TableLike = self.root;
+        for (i, segment) in path.iter().enumerate() {
+            let last = i + 1 == path.len();
+            if key && last {
+                return table.key(segment)?.span();
+            }
+            let item = table.get(segment)?;
+            if last {
+                return item.span();
+            }
+            if let Some(array) = item.as_array() {
+                let value = array.get(path[i + 1].parse().ok()?)?;
+                return if i + 2 == path.len() {
+                    value.span()
+                } else {
+                    None
+                };
+            }
+            table = item.as_table_like()?;
+        }
+        None
+    }
+
+    fn check_references(&mut self, config: &Config) {
+        let emitters = config.emitters.clone().unwrap_or_default();
+        match config.selected_emitters {
+            Some(ref selected) => {
+                for (i, name) in selected.iter().enumerate() {
+                    if !emitters.contains_key(name) {
+                        let index = i.to_string();
+                        self.push(
+                            Severity::Error,
+                            Problem::UnknownEmitter(name.clone()),
+                            &["selected_emitters", &index],
+                        );
+                    }
+                }
+            }
+            None => {
+                // `main` falls back to an emitter called "default" unless there are scenes.
+                if config.scenes.is_none() && !emitters.contains_key("default") {
+                    self.push(
+                        Severity::Warning,
+                        Problem::UnknownEmitter("default".to_string()),
+                        &["selected_emitters"],
+                    );
+                }
+            }
+        }
+
+        let color_pickers = config.color_pickers.clone().unwrap_or_default();
+        let movers = config.movers.clone().unwrap_or_default();
+        for (name, emitter) in sorted(&emitters) {
+            if let Some(ref color_picker) = emitter.color_picker {
+                if !color_pickers.contains_key(color_picker) {
+                    self.push(
+                        Severity::Error,
+                        Problem::DanglingColorPicker(color_picker.clone()),
+                        &["emitters", name, "color_picker"],
+                    );
+                }
+            }
+            if let Some(ref mover) = emitter.mover {
+                if !movers.contains_key(mover) {
+                    self.push(
+                        Severity::Error,
+                        Problem::DanglingMover(mover.clone()),
+                        &["emitters", name, "mover"],
+                    );
+                }
+            }
+        }
+    }
+
+    fn check_scenes(&mut self, config: &Config) {
+        let emitters = config.emitters.clone().unwrap_or_default();
+        let scenes = config.scenes.clone().unwrap_or_default();
+        for (name, scene) in sorted(&scenes) {
+            for (i, emitter) in scene.emitters.iter().enumerate() {
+                if !emitters.contains_key(emitter) {
+                    let index = i.to_string();
+                    self.push(
+                        Severity::Error,
+                        Problem::UnknownEmitter(emitter.clone()),
+                        &["scenes", name, "emitters", &index],
+                    );
+                }
+            }
+        }
+        let sequence = config.sequence.as_ref().and_then(|s| s.scenes.as_ref());
+        for (i, name) in sequence.into_iter().flatten().enumerate() {
+            if !scenes.contains_key(name) {
+                let index = i.to_string();
+                self.push(
+                    Severity::Error,
+                    Problem::UnknownScene(name.clone()),
+                    &["sequence", "scenes", &index],
+                );
+            }
+        }
+    }
+
+    fn check_movers(&mut self, config: &Config) {
+        let movers = config.movers.clone().unwrap_or_default();
+        for (name, mover) in sorted(&movers) {
+            if !MOVER_TYPES.contains(&mover.mover_type.as_str()) {
+                self.push(
+                    Severity::Error,
+                    Problem::UnknownMoverType(mover.mover_type.clone()),
+                    &["movers", name, "mover_type"],
+                );
+            }
+        }
+    }
+
+    fn check_color_pickers(&mut self, config: &Config) {
+        let color_pickers = config.color_pickers.clone().unwrap_or_default();
+        for (name, cp) in sorted(&color_pickers) {
+            for (key, value, max) in scalar_fields(cp) {
+                self.check_range(value, 0.0, max, &["color_pickers", name, key]);
+            }
+            for (key, range, max) in range_fields(cp) {
+                if let Some(range) = range {
+                    for (i, value) in [range.x, range.y].iter().enumerate() {
+                        let index = i.to_string();
+                        self.check_range(
+                            Some(*value),
+                            0.0,
+                            max,
+                            &["color_pickers", name, key, &index],
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_expressions(&mut self, config: &Config) {
+        let emitters = config.emitters.clone().unwrap_or_default();
+        for (name, emitter) in sorted(&emitters) {
+            for (key, value) in emitter_animated_fields(emitter) {
+                self.check_expression(value, &["emitters", name, key]);
+            }
+        }
+        let movers = config.movers.clone().unwrap_or_default();
+        for (name, mover) in sorted(&movers) {
+            for (key, value) in mover_animated_fields(mover) {
+                self.check_expression(value, &["movers", name, key]);
+            }
+        }
+    }
+
+    fn check_emission(&mut self, config: &Config) {
+        let emitters = config.emitters.clone().unwrap_or_default();
+        for (name, emitter) in sorted(&emitters) {
+            if let Some(ref emission) = emitter.emission {
+                let path = ["emitters", name, "emission"];
+                self.check_expression(&emission.rate, &[&path[..], &["rate"]].concat());
+                if let Some(Animated::Constant(rate)) = emission.rate {
+                    self.check_range(
+                        Some(rate as f32),
+                        0.0,
+                        f32::INFINITY,
+                        &[&path[..], &["rate"]].concat(),
+                    );
+                }
+                // Bursts are counted off until the next one is in the future, so
+                // the period must be positive.
+                self.check_range(
+                    emission.period,
+                    MIN_DT,
+                    f32::INFINITY,
+                    &[&path[..], &["period"]].concat(),
+                );
+                let start = emission.start.unwrap_or(0.0);
+                if let Some(stop) = emission.stop.filter(|stop| *stop <= start) {
+                    self.push(
+                        Severity::Error,
+                        Problem::OutOfRange {
+                            value: stop,
+                            min: start,
+                            max: f32::INFINITY,
+                        },
+                        &[&path[..], &["stop"]].concat(),
+                    );
+                }
+            }
+        }
+    }
+
+    fn check_boundaries(&mut self, config: &Config) {
+        let window = config.clone().with_defaults();
+        let size = window
+            .window_width
+            .unwrap()
+            .min(window.window_height.unwrap());
+        let emitters = config.emitters.clone().unwrap_or_default();
+        for (name, emitter) in sorted(&emitters) {
+            // A margin of -size / 2 or less turns the edges inside out.
+            if let Some(margin) = emitter.boundary_margin {
+                if 2.0 * margin <= -size {
+                    self.push(
+                        Severity::Error,
+                        Problem::OutOfRange {
+                            value: margin,
+                            min: -size / 2.0,
+                            max: f32::INFINITY,
+                        },
+                        &["emitters", name, "boundary_margin"],
+                    );
+                }
+            }
+            self.check_range(
+                emitter.restitution,
+                0.0,
+                1.0,
+                &["emitters", name, "restitution"],
+            );
+        }
+    }
+
+    fn check_attractors(&mut self, config: &Config) {
+        let attractors = config.attractors.clone().unwrap_or_default();
+        let movers = config.movers.clone().unwrap_or_default();
+        for (name, attractor) in sorted(&attractors) {
+            if let Some(ref mover) = attractor.mover {
+                if !movers.contains_key(mover) {
+                    self.push(
+                        Severity::Error,
+                        Problem::DanglingMover(mover.clone()),
+                        &["attractors", name, "mover"],
+                    );
+                }
+            }
+            self.check_expression(&attractor.strength, &["attractors", name, "strength"]);
+            self.check_range(
+                attractor.falloff,
+                0.0,
+                4.0,
+                &["attractors", name, "falloff"],
+            );
+            self.check_range(
+                attractor.radius,
+                0.0,
+                f32::INFINITY,
+                &["attractors", name, "radius"],
+            );
+        }
+        let emitters = config.emitters.clone().unwrap_or_default();
+        for (name, emitter) in sorted(&emitters) {
+            for (i, attractor) in emitter.attractors.iter().flatten().enumerate() {
+                if !attractors.contains_key(attractor) {
+                    let index = i.to_string();
+                    self.push(
+                        Severity::Error,
+                        Problem::UnknownAttractor(attractor.clone()),
+                        &["emitters", name, "attractors", &index],
+                    );
+                }
+            }
+        }
+    }
+
+    fn check_all_forces(&mut self, config: &Config) {
+        let dt = config.clone().with_defaults().dt.unwrap();
+        let ticks = dt * TICKS_PER_SECOND as f32;
+        self.check_forces(&config.forces, ticks, &["forces"]);
+        let scenes = config.scenes.clone().unwrap_or_default();
+        for (name, scene) in sorted(&scenes) {
+            self.check_forces(&scene.forces, ticks, &["scenes", name, "forces"]);
+        }
+        let emitters = config.emitters.clone().unwrap_or_default();
+        for (name, emitter) in sorted(&emitters) {
+            self.check_forces(&emitter.forces, ticks, &["emitters", name, "forces"]);
+        }
+    }
+
+    /// `ticks` is how many ticks each step of `dt` moves particles on by.
+    fn check_forces(&mut self, forces: &Option<ForcesConfig>, ticks: f32, path: &[&str]) {
+        let forces = match forces {
+            Some(forces) => forces,
+            None => return,
+        };
+        for (key, value, max) in [
+            ("turbulence", forces.turbulence, f32::INFINITY),
+            ("turbulence_scale", forces.turbulence_scale, f32::INFINITY),
+            // More drag than this overshoots, reversing particles each step.
+            ("linear_drag", forces.linear_drag, 1.0 / ticks),
+            ("quadratic_drag", forces.quadratic_drag, 1.0 / ticks),
+        ] {
+            self.check_range(value, 0.0, max, &[path, &[key]].concat());
+        }
+    }
+
+    fn check_noise(&mut self, config: &Config) {
+        let emitters = config.emitters.clone().unwrap_or_default();
+        for (name, emitter) in sorted(&emitters) {
+            if emitter.noise_field != Some(true) {
+                for (key, value) in [
+                    ("noise_curl", &emitter.noise_curl),
+                    ("noise_strength", &emitter.noise_strength),
+                ] {
+                    if value.is_some() {
+                        self.push(
+                            Severity::Warning,
+                            Problem::Ignored("noise_field = true".to_string()),
+                            &["emitters", name, key],
+                        );
+                    }
+                }
+            }
+            let noise = match emitter.noise {
+                Some(ref noise) => noise,
+                None => continue,
+            };
+            for (key, value, min, max) in [
+                // The noise crate caps fractals at 32 octaves.
+                ("octaves", noise.octaves.map(|o| o as f32), 1.0, 32.0),
+                (
+                    "frequency",
+                    noise.frequency,
+                    f32::MIN_POSITIVE,
+                    f32::INFINITY,
+                ),
+                (
+                    "lacunarity",
+                    noise.lacunarity,
+                    f32::MIN_POSITIVE,
+                    f32::INFINITY,
+                ),
+                ("persistence", noise.persistence, 0.0, f32::INFINITY),
+                ("warp", noise.warp, 0.0, f32::INFINITY),
+                (
+                    "warp_frequency",
+                    noise.warp_frequency,
+                    f32::MIN_POSITIVE,
+                    f32::INFINITY,
+                ),
+            ] {
+                self.check_range(value, min, max, &["emitters", name, "noise", key]);
+            }
+        }
+    }
+
+    fn check_vortices(&mut self, config: &Config) {
+        let emitters = config.emitters.clone().unwrap_or_default();
+        for (name, emitter) in sorted(&emitters) {
+            for (i, vortex) in emitter.vortices.iter().flatten().enumerate() {
+                let index = i.to_string();
+                let path = ["emitters", name, "vortices", &index];
+                self.check_range(
+                    vortex.falloff,
+                    0.0,
+                    4.0,
+                    &[&path[..], &["falloff"]].concat(),
+                );
+                self.check_range(
+                    vortex.radius,
+                    0.0,
+                    f32::INFINITY,
+                    &[&path[..], &["radius"]].concat(),
+                );
+            }
+        }
+    }
+
+    fn check_sweep(&mut self, config: &Config) {
+        let sweep = match config.sweep {
+            Some(ref sweep) => sweep,
+            None => return,
+        };
+        for (path, values) in sweep.values.iter() {
+            if let SweepValues::Range { steps, .. } = values {
+                let path = ["sweep", "values", path, "steps"];
+                self.check_range(Some(*steps as f32), 1.0, f32::INFINITY, &path);
+            }
+        }
+    }
+
+    fn check_expression(&mut self, value: &Option<Animated>, path: &[&str]) {
+        if let Some(Animated::Expression(text)) = value {
+            if let Err(e) = expression::parse(text) {
+                self.push(Severity::Error, Problem::Expression(e.to_string()), path);
+            }
+        }
+    }
+
+    fn check_range(&mut self, value: Option<f32>, min: f32, max: f32, path: &[&str]) {
+        if let Some(value) = value {
+            if value < min || value > max {
+                self.push(
+                    Severity::Error,
+                    Problem::OutOfRange { value, min, max },
+                    path,
+                );
+            }
+        }
+    }
+
+    fn check_unknown_keys(&mut self, unknown_keys: &[Vec<String>], strict: bool) {
+        let severity = if strict {
+            Severity::Error
+        } else {
+            Severity::Warning
+        };
+        for path in unknown_keys {
+            let path: Vec<&str> = path.iter().map(|s| s.as_str()).collect();
+            let span = self.span(&path, true);
+            self.report.diagnostics.push(Diagnostic {
+                severity,
+                problem: Problem::UnknownKey(path.last().unwrap_or(&"").to_string()),
+                path: path.join("."),
+                location: span.map(|span| location(self.source, span.start)),
+            });
+        }
+    }
+}
+
+fn sorted<T>(map: &std::collections::HashMap<String, T>) -> Vec<(&str, &T)> {
+    let mut entries: Vec<(&str, &T)> = map.iter().map(|(k, v)| (k.as_str(), v)).collect();
+    entries.sort_by_key(|(k, _)| *k);
+    entries
+}
+
+fn scalar_fields(cp: &ColorPickerConfig) -> [(&'static str, Option<f32>, f32); 4] {
+    [
+        ("hue", cp.hue, 360.0),
+        ("saturation", cp.saturation, 1.0),
+        ("lightness", cp.lightness, 1.0),
+        ("alpha", cp.alpha, 1.0),
+    ]
+}
+
+fn emitter_animated_fields(e: &EmitterConfig) -> [(&'static str, &Option<Animated>); 6] {
+    [
+        ("flight_size", &e.flight_size),
+        ("noise_curl", &e.noise_curl),
+        ("noise_scale", &e.noise_scale),
+        ("noise_strength", &e.noise_strength),
+        ("radius", &e.radius),
+        ("stroke_weight", &e.stroke_weight),
+    ]
+}
+
+fn mover_animated_fields(m: &MoverConfig) -> [(&'static str, &Option<Animated>); 2] {
+    [
+        ("rotation_angle", &m.rotation_angle),
+        ("rotation_speed", &m.rotation_speed),
+    ]
+}
+
+fn range_fields(cp: &ColorPickerConfig) -> [(&'static str, Option<Vec2>, f32); 4] {
+    [
+        ("range_hue", cp.range_hue, 360.0),
+        ("range_saturation", cp.range_saturation, 1.0),
+        ("range_lightness", cp.range_lightness, 1.0),
+        ("range_alpha", cp.range_alpha, 1.0),
+    ]
+}
+
+pub fn location(source: &str, offset: usize) -> Location {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+    Location { line, column }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use toml_edit::ImDocument;
+
+    fn check(text: &str) -> Report {
+        let root = ImDocument::parse(text).unwrap().into_table();
+        let (config, unknown_keys) = crate::config::deserialize(root.clone()).unwrap();
+        validate(&config, &root, text, &unknown_keys)
+    }
+
+    #[test]
+    fn test_valid() {
+        let report = check(
+            r#"
+selected_emitters = ["e"]
+[color_pickers.green]
+hue = 120
+[emitters.e]
+color_picker = "green"
+"#,
+        );
+        assert!(report.diagnostics.is_empty(), "{}", report);
+    }
+
+    #[test]
+    fn test_dangling_references() {
+        let report = check(
+            r#"
+selected_emitters = ["e", "missing"]
+[emitters.e]
+color_picker = "nope"
+mover = "nada"
+"#,
+        );
+        let problems: Vec<Problem> = report.errors().map(|d| d.problem.clone()).collect();
+        assert_eq!(
+            problems,
+            vec![
+                Problem::UnknownEmitter("missing".to_string()),
+                Problem::DanglingColorPicker("nope".to_string()),
+                Problem::DanglingMover("nada".to_string()),
+            ]
+        );
+        let cp = report.errors().nth(1).unwrap();
+        assert_eq!(cp.path, "emitters.e.color_picker");
+        assert_eq!(
+            cp.location,
+            Some(Location {
+                line: 4,
+                column: 16
+            })
+        );
+    }
+
+    #[test]
+    fn test_scene_references() {
+        let report = check(
+            r#"
+[emitters.e]
+[scenes.one]
+emitters = ["e", "f"]
+[sequence]
+scenes = ["one", "two"]
+"#,
+        );
+        let errors: Vec<String> = report.errors().map(|d| d.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "error: scenes.one.emitters.1: unknown emitter \"f\" (line 4, column 18)",
+                "error: sequence.scenes.1: unknown scene \"two\" (line 6, column 18)",
+            ]
+        );
+        assert_eq!(report.warnings().count(), 0);
+    }
+
+    #[test]
+    fn test_out_of_range_and_mover_type() {
+        let report = check(
+            r#"
+selected_emitters = []
+[color_pickers.c]
+hue = 400
+range_saturation = [0.2, 1.5]
+[movers.m]
+mover_type = "spiral"
+inner = [1, 1]
+outer = [1, 1]
+scale = [1, 1]
+"#,
+        );
+        let paths: Vec<&str> = report.errors().map(|d| d.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "movers.m.mover_type",
+                "color_pickers.c.hue",
+                "color_pickers.c.range_saturation.1"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expressions() {
+        let report = check(
+            r#"
+selected_emitters = ["e"]
+[emitters.e]
+noise_strength = "0.5 + 0.2*sin(t*3)"
+radius = "4 + wobble(t)"
+"#,
+        );
+        let errors: Vec<String> = report.errors().map(|d| d.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "error: emitters.e.radius: invalid expression: unknown name \"wobble\" \
+                 at column 5 (line 5, column 10)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_emission() {
+        let report = check(
+            r#"
+selected_emitters = ["e"]
+[emitters.e.emission]
+mode = "burst"
+rate = "10 * (1 + t"
+period = 0
+"#,
+        );
+        let paths: Vec<&str> = report.errors().map(|d| d.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec!["emitters.e.emission.rate", "emitters.e.emission.period"]
+        );
+        let report = check(
+            r#"
+selected_emitters = ["a", "b", "c", "d"]
+[emitters.a.emission]
+mode = "steady"
+rate = -5
+[emitters.b.emission]
+mode = "burst"
+period = -1
+[emitters.c.emission]
+start = 2
+stop = 2
+[emitters.d.emission]
+rate = "-5 * sin(t)"
+period = 0.5
+start = 1
+stop = 3
+"#,
+        );
+        let paths: Vec<&str> = report.errors().map(|d| d.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "emitters.a.emission.rate",
+                "emitters.b.emission.period",
+                "emitters.c.emission.stop",
+            ]
+        );
+        assert!(check("[emitters.e.emission]\nstop = -1\n").has_errors());
+    }
+
+    #[test]
+    fn test_boundaries() {
+        let report = check(
+            r#"
+selected_emitters = ["e"]
+[emitters.e]
+boundary_mode = "bounce"
+restitution = 1.5
+"#,
+        );
+        let paths: Vec<&str> = report.errors().map(|d| d.path.as_str()).collect();
+        assert_eq!(paths, vec!["emitters.e.restitution"]);
+        let report = check(
+            r#"
+window_width = 400
+window_height = 300
+selected_emitters = ["e", "f"]
+[emitters.e]
+boundary_margin = -150
+[emitters.f]
+boundary_margin = -149
+"#,
+        );
+        let paths: Vec<&str> = report.errors().map(|d| d.path.as_str()).collect();
+        assert_eq!(paths, vec!["emitters.e.boundary_margin"]);
+        assert!(check("[emitters.e]\nboundary_margin = -300\n").has_errors());
+        assert!(
+            crate::config::parse_config("[emitters.e]\nboundary_mode = \"teleport\"\n").is_err()
+        );
+    }
+
+    #[test]
+    fn test_attractors() {
+        let report = check(
+            r#"
+selected_emitters = ["e"]
+[emitters.e]
+attractors = ["sun", "moon"]
+[attractors.sun]
+mover = "orbit"
+strength = "sin(t"
+falloff = 5
+radius = -1
+"#,
+        );
+        let paths: Vec<&str> = report.errors().map(|d| d.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "attractors.sun.mover",
+                "attractors.sun.strength",
+                "attractors.sun.falloff",
+                "attractors.sun.radius",
+                "emitters.e.attractors.1",
+            ]
+        );
+        let last = report.errors().last().unwrap().to_string();
+        assert!(last.contains("unknown attractor \"moon\""), "{}", last);
+    }
+
+    #[test]
+    fn test_forces() {
+        let report = check(
+            r#"
+selected_emitters = ["e"]
+[forces]
+gravity = [0, -0.1]
+linear_drag = 2
+[emitters.e.forces]
+turbulence = -1
+[scenes.s]
+emitters = ["e"]
+forces = { quadratic_drag = 0.5, turbulence_scale = -0.01 }
+"#,
+        );
+        let paths: Vec<&str> = report.errors().map(|d| d.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "forces.linear_drag",
+                "scenes.s.forces.turbulence_scale",
+                "emitters.e.forces.turbulence",
+            ]
+        );
+        // Three ticks a step: a third of the speed lost per tick stops a
+        // particle dead, and any more reverses it.
+        let drag = |drag: f32| format!("dt = 0.05\n[forces]\nlinear_drag = {}\n", drag);
+        assert!(!check(&drag(0.3)).has_errors());
+        let report = check(&drag(0.5));
+        assert_eq!(report.errors().next().unwrap().path, "forces.linear_drag");
+    }
+
+    #[test]
+    fn test_noise() {
+        let report = check(
+            r#"
+selected_emitters = ["a", "b"]
+[emitters.a.noise]
+algorithm = "ridged_multi"
+octaves = 40
+lacunarity = 2.5
+[emitters.b.noise]
+algorithm = "worley"
+frequency = 0
+warp = -1
+"#,
+        );
+        let paths: Vec<&str> = report.errors().map(|d| d.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "emitters.a.noise.octaves",
+                "emitters.b.noise.frequency",
+                "emitters.b.noise.warp",
+            ]
+        );
+        let report = check(
+            r#"
+selected_emitters = ["a"]
+[emitters.a]
+noise_curl = "0.5 * sin(t"
+[[emitters.a.vortices]]
+center = [100, 0]
+strength = -0.2
+[[emitters.a.vortices]]
+falloff = 5
+"#,
+        );
+        let paths: Vec<&str> = report.errors().map(|d| d.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec!["emitters.a.noise_curl", "emitters.a.vortices.1.falloff"]
+        );
+        let report = check(
+            r#"
+selected_emitters = ["a", "b"]
+[emitters.a]
+noise_curl = 0.5
+noise_strength = 0.3
+[emitters.b]
+noise_field = true
+noise_curl = 0.5
+"#,
+        );
+        let paths: Vec<&str> = report.warnings().map(|d| d.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec!["emitters.a.noise_curl", "emitters.a.noise_strength"]
+        );
+        let warning = report.warnings().next().unwrap().to_string();
+        assert!(
+            warning.contains("ignored unless noise_field = true"),
+            "{}",
+            warning
+        );
+        let unknown = "[emitters.a.noise]\nalgorithm = \"simplex\"\n";
+        assert!(crate::config::parse_config(unknown).is_err());
+    }
+
+    #[test]
+    fn test_unknown_keys_are_warnings() {
+        let report = check(
+            r#"
+selected_emitters = []
+colour = 1
+[color_pickers.c]
+some_bullshit = 0.7
+"#,
+        );
+        assert!(!report.has_errors());
+        let warnings: Vec<&str> = report.warnings().map(|d| d.path.as_str()).collect();
+        assert_eq!(warnings, vec!["colour", "color_pickers.c.some_bullshit"]);
+        assert_eq!(
+            report.warnings().next().unwrap().location,
+            Some(Location { line: 3, column: 1 })
+        );
+    }
+
+    #[test]
+    fn test_unknown_keys_are_errors_when_strict() {
+        let report = check(
+            r#"
+strict = true
+selected_emitters = ["e"]
+[emitters.e]
+postion = [1, 2]
+"#,
+        );
+        let errors: Vec<&str> = report.errors().map(|d| d.path.as_str()).collect();
+        assert_eq!(errors, vec!["emitters.e.postion"]);
+    }
+}
@@ -0,0 +1,106 @@
+use super::config::{self, Config};
+use super::format::Format;
+use super::validation::Report;
+
+use log::*;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Polls a config file's modification time so a running sketch can pick up
+/// edits without restarting. Checks are throttled to `interval`.
+#[derive(Debug)]
+pub struct ConfigWatcher {
+    path: PathBuf,
+    format: Format,
+    overrides: Vec<String>,
+    modified: Option<SystemTime>,
+    interval: Duration,
+    last_check: Instant,
+}
+
+impl ConfigWatcher {
+    /// Watches `path`, reading it as `format` with `overrides` applied on top.
+    pub fn new(
+        path: impl Into<PathBuf>,
+        format: Format,
+        overrides: Vec<String>,
+        interval: Duration,
+    ) -> Self {
+        let path = path.into();
+        let modified = modified(&path);
+        ConfigWatcher {
+            path,
+            format,
+            overrides,
+            modified,
+            interval,
+            last_check: Instant::now(),
+        }
+    }
+
+    /// Returns the re-parsed config when the file changed since the last poll.
+    /// A file that is missing or unreadable mid-save is treated as unchanged.
+    pub fn poll(&mut self) -> Option<Result<Config, Report>> {
+        if self.last_check.elapsed() < self.interval {
+            return None;
+        }
+        self.last_check = Instant::now();
+
+        let modified = modified(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return None;
+        }
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                debug!("Error reading {:?}: {}", self.path, e);
+                return None;
+            }
+        };
+        self.modified = modified;
+        info!("Reloading {:?}", self.path);
+        Some(config::parse_config_as(
+            &contents,
+            self.format,
+            &self.overrides,
+        ))
+    }
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn test_poll() {
+        let path = std::env::temp_dir().join("particle_watcher_test.toml");
+        fs::write(&path, "selected_emitters = []\n").unwrap();
+        let mut watcher =
+            ConfigWatcher::new(&path, Format::Toml, Vec::new(), Duration::from_secs(0));
+        assert!(watcher.poll().is_none());
+
+        let touch = |contents: &str, secs: u64| {
+            fs::write(&path, contents).unwrap();
+            let time = SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(time)
+                .unwrap();
+        };
+        touch("selected_emitters = []\nseed = 7\n", 1_000);
+        let config = watcher.poll().unwrap().unwrap();
+        assert_eq!(config.seed, Some(7));
+        assert!(watcher.poll().is_none());
+
+        touch("selected_emitters = [\n", 2_000);
+        assert!(watcher.poll().unwrap().is_err());
+    }
+}
@@ -0,0 +1,614 @@
+use super::attractor::{Attractor, AttractorState};
+use super::config::Config;
+use super::emitter::{Bounds, Emitter, EmitterState};
+use super::integrator::Integrator;
+use super::particle::Particle;
+use super::scene::{Sequence, SequenceState};
+use super::session::Event;
+use super::snapshot::Snapshot;
+use super::stats::{EmitterStats, FrameStats};
+
+use log::*;
+use nannou::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Seconds per unit of sketch time `t`, as seen by movers and expressions:
+/// 360 frames at the 60 fps they were first tuned at.
+pub const SECONDS_PER_T: f64 = 6.0;
+
+/// Ticks per second. Speeds, forces and life spans in a config are per tick.
+pub const TICKS_PER_SECOND: f64 = 60.0;
+
+/// Bounds on a config's `dt`, in seconds.
+pub const MIN_DT: f32 = 0.0001;
+pub const MAX_DT: f32 = 0.25;
+
+/// Most real time one `advance` will catch up on, in seconds, so a stall
+/// doesn't leave the simulation forever behind.
+pub const MAX_ADVANCE: f64 = 0.25;
+
+/// Every emitter a config describes, stepped one `dt` at a time. Nothing here
+/// needs a window, so a `World` can be driven from tests and batch tools as well
+/// as from the sketch. Every random draw comes from per-emitter streams seeded
+/// by the config's `seed`, and time moves in fixed steps of the config's `dt`,
+/// so the same config always plays out the same way at any frame rate.
+#[derive(Debug)]
+pub struct World {
+    // The config last loaded, with defaults filled in.
+    config: Config,
+    emitters: Vec<Emitter>,
+    attractors: Vec<Attractor>,
+    bounds: Bounds,
+    sequence: Option<Sequence>,
+    dt: f64,
+    integrator: Integrator,
+    // Real time not yet simulated, in seconds.
+    accumulator: f64,
+    time: f64,
+    frame: u64,
+    // Stats from each step since they were last taken, when collecting.
+    stats: Option<Vec<FrameStats>>,
+}
+
+/// The parts of a `World` that change as it runs.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct WorldState {
+    pub frame: u64,
+    pub time: f64,
+    pub accumulator: f64,
+    pub sequence: Option<SequenceState>,
+    pub emitters: Vec<EmitterState>,
+    pub attractors: Vec<AttractorState>,
+}
+
+impl World {
+    pub fn new(config: &Config, bounds: Bounds) -> Self {
+        let mut world = World {
+            config: config.clone(),
+            emitters: Vec::new(),
+            attractors: Vec::new(),
+            bounds,
+            sequence: None,
+            dt: 0.0,
+            integrator: Integrator::default(),
+            accumulator: 0.0,
+            time: 0.0,
+            frame: 0,
+            stats: None,
+        };
+        world.load(config, false);
+        world
+    }
+
+    /// Rebuilds the emitters from `config` without restarting the clock. With
+    /// `keep_particles`, live particles move over to the same-named new emitter.
+    /// Every random stream restarts from the config's seed.
+    pub fn load(&mut self, config: &Config, keep_particles: bool) {
+        let config = config.clone().with_defaults();
+        self.config = config.clone();
+        self.dt = config.dt.unwrap() as f64;
+        self.integrator = config.integrator.unwrap();
+        self.sequence = Sequence::from_config(&config, self.frame);
+        let names = match self.sequence {
+            Some(ref sequence) => sequence.emitter_names(),
+            None => config.selected_emitters.clone().unwrap(),
+        };
+        let mut emitters = build_emitters(&config, &names, self.bounds);
+        if keep_particles {
+            for old in self.emitters.iter_mut() {
+                if let Some(e) = emitters.iter_mut().find(|e| e.name() == old.name()) {
+                    e.particles = std::mem::take(&mut old.particles);
+                }
+            }
+        }
+        self.emitters = emitters;
+        self.attractors = build_attractors(&config);
+        self.show_scene(0);
+    }
+
+    /// Rebuilds the world exactly as it was when `snapshot` was taken.
+    pub fn from_snapshot(snapshot: Snapshot, bounds: Bounds) -> Self {
+        let mut world = World::new(&snapshot.config, bounds);
+        world.restore(snapshot.world);
+        world
+    }
+
+    /// The current config and state, to resume from later.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot::new(self.config.clone(), self.state())
+    }
+
+    pub fn state(&self) -> WorldState {
+        WorldState {
+            frame: self.frame,
+            time: self.time,
+            accumulator: self.accumulator,
+            sequence: self.sequence.as_ref().map(Sequence::state),
+            emitters: self.emitters.iter().map(Emitter::state).collect(),
+            attractors: self.attractors.iter().map(Attractor::state).collect(),
+        }
+    }
+
+    /// Puts the world back in `state`, which should come from a world with the
+    /// same config. Emitter and attractor states are matched up by name.
+    pub fn restore(&mut self, state: WorldState) {
+        self.frame = state.frame;
+        self.time = state.time;
+        self.accumulator = state.accumulator;
+        if let (Some(sequence), Some(state)) = (self.sequence.as_mut(), state.sequence) {
+            sequence.restore(state);
+        }
+        self.set_forces();
+        for emitter_state in state.emitters {
+            match self
+                .emitters
+                .iter_mut()
+                .find(|e| e.name() == emitter_state.name)
+            {
+                Some(e) => e.restore(emitter_state),
+                None => warn!("No emitter named {:?} to restore", emitter_state.name),
+            }
+        }
+        for attractor_state in state.attractors {
+            match self
+                .attractors
+                .iter_mut()
+                .find(|a| a.name() == attractor_state.name)
+            {
+                Some(a) => a.restore(attractor_state),
+                None => warn!("No attractor named {:?} to restore", attractor_state.name),
+            }
+        }
+    }
+
+    /// Simulates `elapsed` seconds of real time, plus whatever was left over
+    /// last time, in whole steps of `dt`. Returns how many steps ran.
+    pub fn advance(&mut self, elapsed: f64) -> u32 {
+        self.accumulator += elapsed.min(MAX_ADVANCE);
+        let mut steps = 0;
+        while self.accumulator >= self.dt {
+            self.accumulator -= self.dt;
+            self.step();
+            steps += 1;
+        }
+        steps
+    }
+
+    /// Advances the simulation by one step of `dt`.
+    pub fn step(&mut self) {
+        let frame = self.frame;
+        let t = self.t();
+        let ticks = (self.dt * TICKS_PER_SECOND) as f32;
+        self.change_scene(Sequence::update);
+        for a in self.attractors.iter_mut() {
+            a.update(t, frame);
+        }
+        for e in self.emitters.iter_mut() {
+            e.update(t, frame, ticks, self.integrator, &self.attractors);
+        }
+        for e in self.emitters.iter_mut() {
+            e.emit_scheduled(self.time, self.dt);
+        }
+        self.frame += 1;
+        self.time += self.dt;
+        if let Some(mut stats) = self.stats.take() {
+            stats.push(self.stats());
+            self.stats = Some(stats);
+        }
+    }
+
+    /// Measurements of every emitter as of the last step.
+    pub fn stats(&self) -> FrameStats {
+        FrameStats {
+            frame: self.frame,
+            time: self.time,
+            emitters: self.emitters.iter().map(EmitterStats::of).collect(),
+        }
+    }
+
+    /// Starts or stops keeping the stats of every step, for `take_stats`.
+    pub fn collect_stats(&mut self, collect: bool) {
+        self.stats = if collect { Some(Vec::new()) } else { None };
+    }
+
+    /// The stats of each step since the last call, oldest first.
+    pub fn take_stats(&mut self) -> Vec<FrameStats> {
+        self.stats.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    pub fn display(&self, draw: &Draw) {
+        for e in self.emitters.iter() {
+            e.display(draw);
+        }
+    }
+
+    /// Steps taken so far.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Simulated seconds so far.
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Sketch time of the next step.
+    pub fn t(&self) -> f32 {
+        (self.time / SECONDS_PER_T) as f32
+    }
+
+    /// Seconds per step.
+    pub fn dt(&self) -> f64 {
+        self.dt
+    }
+
+    pub fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    pub fn emitters(&self) -> &[Emitter] {
+        &self.emitters
+    }
+
+    pub fn attractors(&self) -> &[Attractor] {
+        &self.attractors
+    }
+
+    pub fn particles(&self) -> impl Iterator<Item = Particle> + '_ {
+        self.emitters.iter().flat_map(|e| e.particles.iter())
+    }
+
+    /// Does what `event` does to the simulation; a frame event takes its steps.
+    /// Captures, snapshots and the start of a session are left to the sketch.
+    pub fn apply(&mut self, event: &Event) {
+        match event {
+            Event::TogglePause => self.toggle_pause(),
+            Event::NextScene => self.next_scene(),
+            Event::PreviousScene => self.previous_scene(),
+            Event::Load {
+                config,
+                keep_particles,
+                ..
+            } => self.load(config, *keep_particles),
+            Event::Frame { steps } => {
+                for _ in 0..*steps {
+                    self.step();
+                }
+            }
+            Event::Start { .. } | Event::Capture | Event::SaveSnapshot | Event::End => {}
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        for e in self.emitters.iter_mut() {
+            e.toggle_pause();
+        }
+    }
+
+    /// Switches to the following scene, if the config has scenes.
+    pub fn next_scene(&mut self) {
+        self.change_scene(Sequence::next);
+    }
+
+    /// Switches to the preceding scene, if the config has scenes.
+    pub fn previous_scene(&mut self) {
+        self.change_scene(Sequence::previous);
+    }
+
+    /// Applies `change` to the sequence, fading to the new scene if it changed.
+    fn change_scene(&mut self, change: fn(&mut Sequence, u64) -> bool) {
+        let frame = self.frame;
+        let fade_steps = match self.sequence {
+            Some(ref mut sequence) => {
+                if !change(sequence, frame) {
+                    return;
+                }
+                sequence.fade_steps
+            }
+            None => return,
+        };
+        self.show_scene(fade_steps);
+    }
+
+    /// Gives every emitter the current scene's forces, or the top-level ones.
+    fn set_forces(&mut self) {
+        let global = self.config.forces.clone().unwrap();
+        let forces = match self.sequence {
+            Some(ref sequence) => match sequence.current().forces {
+                Some(ref forces) => forces.clone().or(&global),
+                None => global,
+            },
+            None => global,
+        };
+        for e in self.emitters.iter_mut() {
+            e.set_forces(&forces);
+        }
+    }
+
+    /// Fades in the current scene's emitters and fades out the rest, and
+    /// switches to the scene's forces.
+    fn show_scene(&mut self, fade_steps: u64) {
+        self.set_forces();
+        let scene = match self.sequence {
+            Some(ref sequence) => sequence.current(),
+            None => return,
+        };
+        for e in self.emitters.iter_mut() {
+            let visible = scene.emitters.iter().any(|name| name == e.name());
+            e.fade_to(visible, fade_steps);
+        }
+    }
+}
+
+/// Builds the named emitters. `config` must have been through `with_defaults`.
+fn build_emitters(config: &Config, names: &[String], bounds: Bounds) -> Vec<Emitter> {
+    let color_pickers = config.color_pickers.as_ref().unwrap();
+    let movers = config.movers.as_ref().unwrap();
+    let emitter_configs = config.emitters.as_ref().unwrap();
+    let seed = config.seed.unwrap();
+    let mut emitters = Vec::new();
+    for name in names {
+        info!("emitter: {:?}", name);
+        let emitter_config = match emitter_configs.get(name) {
+            Some(emitter_config) => emitter_config.clone(),
+            None => {
+                warn!("No emitter named {:?}; skipping", name);
+                continue;
+            }
+        };
+        emitters.push(Emitter::from_config(
+            name.to_string(),
+            emitter_config,
+            color_pickers,
+            movers,
+            bounds,
+            seed,
+        ));
+    }
+    emitters
+}
+
+fn build_attractors(config: &Config) -> Vec<Attractor> {
+    let movers = config.movers.as_ref().unwrap();
+    let mut attractors: Vec<Attractor> = config
+        .attractors
+        .iter()
+        .flatten()
+        .map(|(name, a)| Attractor::from_config(name.clone(), a.clone(), movers))
+        .collect();
+    // Sum the pulls in the same order every run.
+    attractors.sort_by(|a, b| a.name().cmp(b.name()));
+    attractors
+}
+
+/// A world for `text` in a 600 by 600 window centred on the origin.
+#[cfg(test)]
+pub(crate) fn test_world(text: &str) -> World {
+    let bounds = Bounds {
+        top: 300.0,
+        bottom: -300.0,
+        left: -300.0,
+        right: 300.0,
+    };
+    World::new(&crate::config::parse_config(text).unwrap(), bounds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_config;
+
+    #[test]
+    fn test_step() {
+        let mut world = test_world(
+            r#"
+selected_emitters = ["e"]
+[emitters.e]
+flight_size = 5
+life_span = 20
+randomize_velocity = false
+initial_velocity = [1, 0]
+"#,
+        );
+        assert_eq!(world.emitters().len(), 1);
+        for _ in 0..200 {
+            world.step();
+        }
+        assert_eq!(world.frame(), 200);
+        // Particles live for 10 frames, losing 2 of their life span each step.
+        let count = world.particles().count();
+        assert!(count <= 5 * 11, "{}", count);
+        for p in world.particles() {
+            assert_eq!(p.velocity, vec2(1.0, 0.0));
+            assert!(p.position.x <= 11.0);
+        }
+    }
+
+    #[test]
+    fn test_attractors() {
+        let mut world = test_world(
+            r#"
+selected_emitters = ["pulled", "free"]
+[defaults.emitter]
+randomize_velocity = false
+flight_size = 1
+[emitters.pulled]
+attractors = ["sink"]
+[emitters.free]
+attractors = []
+[attractors.sink]
+position = [100, 0]
+strength = 0.01
+falloff = 0
+"#,
+        );
+        for _ in 0..30 {
+            world.step();
+        }
+        let velocities =
+            |e: &Emitter| -> Vec<Vec2> { e.particles.iter().map(|p| p.velocity).collect() };
+        let pulled = velocities(&world.emitters()[0]);
+        assert!(!pulled.is_empty());
+        assert!(pulled.iter().all(|v| v.x > 0.0 && v.y == 0.0));
+        assert!(velocities(&world.emitters()[1])
+            .iter()
+            .all(|v| *v == vec2(0.0, 0.0)));
+    }
+
+    #[test]
+    fn test_forces_by_scene_and_emitter() {
+        let mut world = test_world(
+            r#"
+[forces]
+gravity = [0, -0.5]
+[defaults.emitter]
+randomize_velocity = false
+emission = { mode = "steady", rate = 60 }
+[emitters.a]
+[emitters.b]
+forces = { gravity = [0, 0], wind = [0.25, 0] }
+[scenes.calm]
+emitters = ["a", "b"]
+[scenes.windy]
+emitters = ["a", "b"]
+forces = { wind = [1, 0] }
+"#,
+        );
+        let velocities = |world: &World| -> Vec<Vec2> {
+            world
+                .emitters()
+                .iter()
+                .map(|e| e.particles.get(0).velocity)
+                .collect()
+        };
+        for _ in 0..3 {
+            world.step();
+        }
+        // Two updates of the first particle, whose forces then clear.
+        assert_eq!(velocities(&world), vec![vec2(0.0, -1.0), vec2(0.5, 0.0)]);
+        world.next_scene();
+        world.step();
+        assert_eq!(velocities(&world), vec![vec2(1.0, -1.5), vec2(0.75, 0.0)]);
+    }
+
+    #[test]
+    fn test_scenes_and_reload() {
+        let mut world = test_world(
+            r#"
+[emitters.a]
+[emitters.b]
+[scenes.one]
+emitters = ["a"]
+[scenes.two]
+emitters = ["b"]
+"#,
+        );
+        let names: Vec<&str> = world.emitters().iter().map(|e| e.name()).collect();
+        assert_eq!(names, vec!["a", "b"]);
+        world.next_scene();
+        world.step();
+        assert_eq!(world.frame(), 1);
+
+        world.load(&parse_config("[emitters.default]").unwrap(), false);
+        assert_eq!(world.emitters()[0].name(), "default");
+        assert_eq!(world.frame(), 1);
+    }
+
+    #[test]
+    fn test_same_seed_same_run() {
+        let text = r#"
+seed = 5
+selected_emitters = ["a", "b"]
+[emitters.a]
+color_picker = "range"
+randomize_position = true
+[emitters.b]
+seed = 9
+color_picker = "range"
+noise_field = true
+noise_scale = 0.01
+noise_strength = 0.5
+[color_pickers.range]
+num_colors = 4
+range_hue = [0, 360]
+range_lightness = [0.3, 0.7]
+"#;
+        let run = |text: &str| {
+            let mut world = test_world(text);
+            for _ in 0..100 {
+                world.step();
+            }
+            world
+                .particles()
+                .map(|p| format!("{:?}", p))
+                .collect::<Vec<String>>()
+        };
+        let particles = run(text);
+        assert!(!particles.is_empty());
+        assert_eq!(particles, run(text));
+        assert_ne!(particles, run(&text.replace("seed = 5", "seed = 6")));
+    }
+
+    #[test]
+    fn test_animated_flow_field() {
+        let text = r#"
+selected_emitters = ["e"]
+[emitters.e]
+noise_field = true
+noise_scale = 0.01
+noise_strength = 0.5
+[emitters.e.emission]
+mode = "steady"
+rate = 60
+"#;
+        let run = |extra: &str| {
+            let mut world = test_world(&text.replace(
+                "[emitters.e.emission]",
+                &format!("{}\n[emitters.e.emission]", extra),
+            ));
+            for _ in 0..60 {
+                world.step();
+            }
+            world
+                .particles()
+                .map(|p| format!("{:?}", p))
+                .collect::<Vec<String>>()
+        };
+        let still = run("");
+        assert_eq!(still, run("noise_time_scale = 0\nnoise_drift = [0, 0]"));
+        let evolving = run("noise_time_scale = 0.01");
+        let drifting = run("noise_drift = [1, 0]");
+        assert_ne!(still, evolving);
+        assert_ne!(still, drifting);
+        assert_ne!(evolving, drifting);
+    }
+
+    #[test]
+    fn test_frame_rate_independence() {
+        let text = r#"
+dt = 0.015625
+integrator = "rk4"
+selected_emitters = ["e"]
+[emitters.e]
+noise_field = true
+noise_scale = 0.01
+noise_strength = 0.2
+"#;
+        // Two seconds at 32 and at 128 fps, in 64ths of a second so the sums are exact.
+        let run = |fps: u32| {
+            let mut world = test_world(text);
+            let steps: u32 = (0..2 * fps).map(|_| world.advance(1.0 / fps as f64)).sum();
+            assert_eq!(steps, 128);
+            assert_eq!(world.time(), 2.0);
+            world
+                .particles()
+                .map(|p| format!("{:?}", p))
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(run(32), run(128));
+
+        // A long stall only catches up a little.
+        let mut world = test_world(text);
+        assert_eq!(world.advance(10.0), (MAX_ADVANCE / 0.015625) as u32);
+    }
+}